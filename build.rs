@@ -0,0 +1,12 @@
+fn main() {
+    println!("cargo:rerun-if-changed=proto");
+    // lets the Node.js module link against the symbols node provides
+    #[cfg(feature = "node")]
+    napi_build::setup();
+    // the service `s2::grpc` serves, compiled without needing protoc
+    #[cfg(feature = "grpc")]
+    {
+        let descriptors = protox::compile(["proto/s2.proto"], ["proto"]).unwrap();
+        tonic_build::configure().compile_fds(descriptors).unwrap();
+    }
+}
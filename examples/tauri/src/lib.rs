@@ -0,0 +1,65 @@
+//! The commands `s2::tauri_commands!` defines, registered with
+//! `s2::tauri_handler!` on an app with tauri's mock runtime.
+
+s2::tauri_commands!();
+
+/// An app managing the stacks at `path`, with the commands registered.
+pub fn app(path: &str) -> tauri::App<tauri::test::MockRuntime> {
+    let stacks: s2::tauri::SharedStacks = std::sync::Mutex::new(s2::Stacks::open(path));
+    tauri::test::mock_builder()
+        .manage(stacks)
+        .invoke_handler(s2::tauri_handler!())
+        .build(tauri::test::mock_context(tauri::test::noop_assets()))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use tauri::ipc::{CallbackFn, InvokeBody};
+    use tauri::test::{get_ipc_response, INVOKE_KEY};
+    use tauri::webview::{InvokeRequest, WebviewWindowBuilder};
+    use tauri::WebviewUrl;
+
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = app(dir.path().to_str().unwrap());
+        let webview = WebviewWindowBuilder::new(&app, "main", WebviewUrl::default())
+            .build()
+            .unwrap();
+        let invoke = |cmd: &str, args: Value| {
+            let request = InvokeRequest {
+                cmd: cmd.to_string(),
+                callback: CallbackFn(0),
+                error: CallbackFn(1),
+                url: "http://tauri.localhost".parse().unwrap(),
+                body: InvokeBody::Json(args),
+                headers: Default::default(),
+                invoke_key: INVOKE_KEY.to_string(),
+            };
+            get_ipc_response(&webview, request).map(|body| body.deserialize::<Value>().unwrap())
+        };
+
+        // packets come back in the wire format
+        let stack = invoke("s2_add", json!({"content": "Notes"})).unwrap();
+        assert_eq!(stack["type"], "add");
+        let stack_id = &stack["data"]["id"];
+        let note = invoke("s2_add", json!({"content": "a note", "stackId": stack_id})).unwrap();
+        let note_id = &note["data"]["id"];
+        let updated = invoke("s2_update", json!({"id": note_id, "content": "edited"})).unwrap();
+        assert_eq!(updated["type"], "update");
+        assert_eq!(&updated["data"]["source_id"], note_id);
+
+        let children = invoke("s2_list", json!({"stackId": stack_id})).unwrap();
+        assert_eq!(children.as_array().unwrap().len(), 1);
+        let content = invoke("s2_content", json!({"id": note_id})).unwrap();
+        assert_eq!(content, json!(b"edited"));
+
+        let deleted = invoke("s2_delete", json!({"id": note_id})).unwrap();
+        assert_eq!(deleted["type"], "delete");
+        assert!(invoke("s2_delete", json!({"id": note_id})).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! Exports a stack as a zip of plain files with a JSON manifest, for tools
+//! that don't read stores, and imports such a zip back as a new stack.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+
+use scru128::Scru128Id;
+use serde::{Deserialize, Serialize};
+use ssri::Integrity;
+
+use crate::store::{AddError, CasError, MimeType, Store};
+use crate::view::{Item, View};
+
+const MANIFEST: &str = "manifest.json";
+
+/// Written to `manifest.json` in the zip.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub title: String,
+    /// Depth first, so each entry's stack comes before it.
+    pub items: Vec<Entry>,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: Scru128Id,
+    pub hash: Integrity,
+    pub mime_type: String,
+    /// The file's name in the zip.
+    pub file: String,
+    /// The exported stack, or the stack within it holding the item.
+    pub stack_id: Scru128Id,
+    /// Milliseconds since the epoch.
+    pub created: u64,
+    pub last_touched: u64,
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(std::io::Error),
+    /// Exported content couldn't be read, e.g. a corrupt blob.
+    Cas(CasError),
+    /// The stack to export isn't in the view.
+    UnknownStack(Scru128Id),
+    Zip(zip::result::ZipError),
+    Manifest(serde_json::Error),
+    MissingFile(String),
+    /// A file's content doesn't match its manifest entry.
+    HashMismatch(Integrity),
+    Add(AddError),
+}
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveError::Io(err) => write!(f, "couldn't access archive: {}", err),
+            ArchiveError::Cas(err) => write!(f, "{}", err),
+            ArchiveError::UnknownStack(id) => write!(f, "no stack {}", id),
+            ArchiveError::Zip(err) => write!(f, "archive is corrupt: {}", err),
+            ArchiveError::Manifest(err) => write!(f, "manifest is corrupt: {}", err),
+            ArchiveError::MissingFile(file) => write!(f, "archive is missing {}", file),
+            ArchiveError::HashMismatch(hash) => write!(f, "content doesn't match {}", hash),
+            ArchiveError::Add(err) => write!(f, "couldn't add content: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+/// Writes each item beneath `stack_id` to a zip at `path` as a file named
+/// from its terse preview, with an extension for its MIME type. Stacks within
+/// the stack are written as files too. Protected content is left out, even
+/// with its stack's key.
+pub fn export(
+    store: &Store,
+    view: &View,
+    stack_id: Scru128Id,
+    path: &str,
+) -> Result<Manifest, ArchiveError> {
+    let stack = view
+        .get(&stack_id)
+        .ok_or(ArchiveError::UnknownStack(stack_id))?;
+    let title = store
+        .cas_read(&stack.hash)
+        .ok()
+        .flatten()
+        .map(|content| String::from_utf8_lossy(&content).trim().to_string())
+        .unwrap_or_default();
+
+    let file = std::fs::File::create(path).map_err(ArchiveError::Io)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let mut manifest = Manifest {
+        title,
+        items: Vec::new(),
+    };
+    let mut names = HashSet::new();
+    let mut pending: Vec<&Item> = view.iter_children(stack).collect();
+    pending.reverse();
+    while let Some(item) = pending.pop() {
+        pending.extend(
+            view.iter_children(item)
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev(),
+        );
+        let Some(meta) = store.content_meta(&item.hash) else {
+            continue;
+        };
+        if meta.protected_by.is_some() {
+            continue;
+        }
+        let Some(content) = store.cas_read(&item.hash).map_err(ArchiveError::Cas)? else {
+            continue;
+        };
+        let file = file_name(&meta.terse, &meta.mime_type, &mut names);
+        zip.start_file(file.as_str(), zip::write::SimpleFileOptions::default())
+            .map_err(ArchiveError::Zip)?;
+        zip.write_all(&content).map_err(ArchiveError::Io)?;
+        manifest.items.push(Entry {
+            id: item.id,
+            hash: item.hash.clone(),
+            mime_type: meta.mime_type.as_str().to_string(),
+            file,
+            stack_id: item.stack_id.unwrap_or(stack_id),
+            created: item.id.timestamp(),
+            last_touched: item.last_touched.timestamp(),
+        });
+    }
+
+    zip.start_file(MANIFEST, zip::write::SimpleFileOptions::default())
+        .map_err(ArchiveError::Zip)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest).map_err(ArchiveError::Manifest)?;
+    zip.finish().map_err(ArchiveError::Zip)?;
+    Ok(manifest)
+}
+
+/// Adds the zip at `path` to `store` as a new stack titled from its
+/// manifest, checking every file against its hash before adding anything.
+/// Returns the new stack's id.
+pub fn import(store: &mut Store, path: &str) -> Result<Scru128Id, ArchiveError> {
+    let file = std::fs::File::open(path).map_err(ArchiveError::Io)?;
+    let mut zip = zip::ZipArchive::new(file).map_err(ArchiveError::Zip)?;
+    let manifest: Manifest = serde_json::from_reader(
+        zip.by_name(MANIFEST)
+            .map_err(|_| ArchiveError::MissingFile(MANIFEST.into()))?,
+    )
+    .map_err(ArchiveError::Manifest)?;
+
+    let mut contents = Vec::new();
+    for entry in &manifest.items {
+        let mut file = zip
+            .by_name(&entry.file)
+            .map_err(|_| ArchiveError::MissingFile(entry.file.clone()))?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).map_err(ArchiveError::Io)?;
+        if entry.hash.check(&content).is_err() {
+            return Err(ArchiveError::HashMismatch(entry.hash.clone()));
+        }
+        contents.push(content);
+    }
+
+    let stack = store
+        .add(manifest.title.as_bytes(), MimeType::TextPlain, None, None)
+        .map_err(ArchiveError::Add)?;
+    let mut ids: HashMap<Scru128Id, Scru128Id> = HashMap::new();
+    for (entry, content) in manifest.items.iter().zip(contents) {
+        let stack_id = ids.get(&entry.stack_id).copied().unwrap_or(stack.id());
+        let packet = store
+            .add(
+                &content,
+                MimeType::from(entry.mime_type.as_str()),
+                Some(stack_id),
+                None,
+            )
+            .map_err(ArchiveError::Add)?;
+        ids.insert(entry.id, packet.id());
+    }
+    Ok(stack.id())
+}
+
+/// A name from `terse` that isn't in `names` yet.
+fn file_name(terse: &str, mime_type: &MimeType, names: &mut HashSet<String>) -> String {
+    let stem: String = terse
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || " -_".contains(c) => c,
+            _ => '-',
+        })
+        .take(40)
+        .collect();
+    let stem = match stem.trim() {
+        "" => "item",
+        stem => stem,
+    };
+    let extension = match mime_type.as_str() {
+        "text/plain" => "txt",
+        "text/markdown" => "md",
+        "text/html" => "html",
+        "text/uri-list" => "uri",
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "application/pdf" => "pdf",
+        "application/json" => "json",
+        _ => "bin",
+    };
+
+    let mut name = format!("{}.{}", stem, extension);
+    let mut n = 1;
+    while !names.insert(name.clone()) {
+        n += 1;
+        name = format!("{} ({}).{}", stem, n, extension);
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_dir = tempfile::tempdir().unwrap();
+        let path = zip_dir.path().join("research.zip");
+        let path = path.to_str().unwrap();
+
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let mut view = View::new();
+        let mut add = |store: &mut Store, content: &[u8], mime_type, stack_id| {
+            let packet = store.add(content, mime_type, stack_id, None).unwrap();
+            view.merge(packet.clone());
+            packet.id()
+        };
+        let research = add(&mut store, b"Research", MimeType::TextPlain, None);
+        add(&mut store, b"a note", MimeType::TextPlain, Some(research));
+        add(&mut store, b"a note", MimeType::TextPlain, Some(research));
+        let sources = add(&mut store, b"Sources", MimeType::TextPlain, Some(research));
+        add(&mut store, b"a/b?c", MimeType::TextPlain, Some(sources));
+
+        let manifest = export(&store, &view, research, path).unwrap();
+        assert_eq!(manifest.title, "Research");
+        let files: Vec<&str> = manifest.items.iter().map(|e| e.file.as_str()).collect();
+        assert_eq!(
+            files,
+            vec!["a note.txt", "a note (2).txt", "Sources.txt", "a-b-c.txt"]
+        );
+        assert_eq!(manifest.items[3].stack_id, sources);
+
+        let stack_id = import(&mut store, path).unwrap();
+        let mut view = View::new();
+        store.scan().for_each(|packet| view.merge(packet.unwrap()));
+        let stack = view.get(&stack_id).unwrap();
+        let contents = |item: &Item| store.cas_read(&item.hash).unwrap().unwrap();
+        assert_eq!(contents(stack), b"Research");
+        let children: Vec<&Item> = view.iter_children(stack).collect();
+        assert_eq!(children.len(), 3);
+        assert_eq!(contents(children[2]), b"Sources");
+        let nested: Vec<Vec<u8>> = view.iter_children(children[2]).map(contents).collect();
+        assert_eq!(nested, vec![b"a/b?c".to_vec()]);
+
+        // errors are returned, rather than panicking
+        let unwritable = zip_dir.path().join("no/such/dir.zip");
+        assert!(matches!(
+            export(&store, &view, research, unwritable.to_str().unwrap()),
+            Err(ArchiveError::Io(_))
+        ));
+        assert!(matches!(
+            export(&store, &view, scru128::new(), path),
+            Err(ArchiveError::UnknownStack(_))
+        ));
+    }
+
+    #[test]
+    fn test_archive_protected() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_dir = tempfile::tempdir().unwrap();
+        let path = zip_dir.path().join("vault.zip");
+        let path = path.to_str().unwrap();
+
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let vault = store
+            .add(b"Vault", MimeType::TextPlain, None, None)
+            .unwrap();
+        let note = store
+            .add(b"a note", MimeType::TextPlain, Some(vault.id()), None)
+            .unwrap();
+        let keyring = HashMap::from([(vault.id(), [1; 32])]);
+        store.set_keyring(Box::new(keyring));
+        store.protect_stack(vault.id()).unwrap();
+        store
+            .add(b"hunter2", MimeType::TextPlain, Some(vault.id()), None)
+            .unwrap();
+        let mut view = View::new();
+        store.scan().for_each(|packet| view.merge(packet.unwrap()));
+        assert_ne!(view.get(&note.id()).unwrap().hash, *note.hash().unwrap());
+
+        // sealed content is left out, though the key is there to read it
+        let manifest = export(&store, &view, vault.id(), path).unwrap();
+        assert!(manifest.items.is_empty());
+    }
+}
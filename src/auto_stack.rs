@@ -0,0 +1,89 @@
+//! Groups items added without a stack, so the root doesn't become one long
+//! flat list, see `StoreOptions::auto_stack`.
+
+use std::time::Duration;
+
+use crate::kind::ContentKind;
+
+/// How `Store::add` picks a stack for an item added without one. Stacks are
+/// created as needed, as items whose content names the group, and reused
+/// while they're around. Protected stacks are never picked.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum AutoStack {
+    /// A stack per source app, see `Source::app`. Items without a source
+    /// stay at the root.
+    BySource,
+    /// A stack per session: an item added within `gap` of the last one
+    /// auto-stacked joins its stack, otherwise it starts a new one.
+    BySession { gap: Duration },
+    /// A stack per content kind, see `ContentKind`. Items of no particular
+    /// kind stay at the root.
+    ByKind,
+}
+
+impl AutoStack {
+    /// The group an item added at `millis` belongs to, and the content of a
+    /// new stack for it, or `None` to leave the item at the root.
+    pub(crate) fn group(
+        &self,
+        app: Option<&str>,
+        kind: Option<ContentKind>,
+        millis: u64,
+    ) -> Option<(String, String)> {
+        match self {
+            AutoStack::BySource => app.map(|app| (format!("source:{}", app), app.to_string())),
+            AutoStack::BySession { .. } => Some((
+                "session".to_string(),
+                format!("Session {}", format_utc(millis)),
+            )),
+            AutoStack::ByKind => {
+                kind.map(|kind| (format!("kind:{}", kind.name()), kind.to_string()))
+            }
+        }
+    }
+
+    /// Whether an item added at `millis` can join a group's stack last
+    /// joined at `last`.
+    pub(crate) fn joins(&self, last: u64, millis: u64) -> bool {
+        match self {
+            AutoStack::BySession { gap } => millis.saturating_sub(last) <= gap.as_millis() as u64,
+            _ => true,
+        }
+    }
+}
+
+/// `millis` since the epoch as e.g. `2024-03-01 14:05 UTC`.
+fn format_utc(millis: u64) -> String {
+    let minutes = millis / 60_000;
+    let days = (minutes / (24 * 60)) as i64;
+    // from Howard Hinnant's civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        minutes / 60 % 24,
+        minutes % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_utc(1_709_301_900_000), "2024-03-01 14:05 UTC");
+        assert_eq!(format_utc(951_782_400_000), "2000-02-29 00:00 UTC");
+    }
+}
@@ -0,0 +1,291 @@
+//! Passphrase-encrypted export bundles, for moving history through untrusted
+//! channels like cloud drives.
+
+use age::secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use ssri::Integrity;
+
+use crate::export::{self, ExportFilter};
+use crate::store::{CasError, Content, InsertError, MimeType, Packet, Store};
+use crate::view::View;
+
+const FORMAT: u32 = 1;
+
+/// Describes a bundle's contents.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub packets: usize,
+    pub hashes: Vec<Integrity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    manifest: Manifest,
+    packets: Vec<Packet>,
+    blobs: Vec<Blob>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Blob {
+    Plain(MimeType, Vec<u8>),
+    /// Sealed for a protected stack, and carried sealed, with its metadata,
+    /// see `Store::store_sealed`.
+    Sealed(Content, Vec<u8>),
+}
+
+impl Blob {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Blob::Plain(_, bytes) | Blob::Sealed(_, bytes) => bytes,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    Io(std::io::Error),
+    /// Exported content couldn't be read, e.g. a corrupt blob.
+    Cas(CasError),
+    /// Exported content isn't stored.
+    Missing(Integrity),
+    Encrypt(age::EncryptError),
+    Decrypt(age::DecryptError),
+    /// The decrypted bundle doesn't decode, or doesn't match its manifest.
+    Corrupt,
+    UnsupportedFormat(u32),
+    /// A blob's content doesn't match its hash.
+    HashMismatch(Integrity),
+}
+
+impl std::fmt::Display for BundleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BundleError::Io(err) => write!(f, "couldn't access bundle: {}", err),
+            BundleError::Cas(err) => write!(f, "{}", err),
+            BundleError::Missing(hash) => write!(f, "content {} isn't readable", hash),
+            BundleError::Encrypt(err) => write!(f, "couldn't encrypt bundle: {}", err),
+            BundleError::Decrypt(err) => write!(f, "couldn't decrypt bundle: {}", err),
+            BundleError::Corrupt => write!(f, "bundle is corrupt"),
+            BundleError::UnsupportedFormat(format) => {
+                write!(f, "unsupported bundle format {}", format)
+            }
+            BundleError::HashMismatch(hash) => write!(f, "content doesn't match {}", hash),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BundleError::Io(err) => Some(err),
+            BundleError::Cas(err) => Some(err),
+            BundleError::Encrypt(err) => Some(err),
+            BundleError::Decrypt(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the packets `filter` selects, their content and a manifest to a
+/// single file at `path`, encrypted with `passphrase`. Returns the manifest,
+/// or an error without writing anything if some content can't be read.
+pub fn export(
+    store: &Store,
+    view: &View,
+    path: &str,
+    filter: &ExportFilter,
+    passphrase: &str,
+) -> Result<Manifest, BundleError> {
+    let packets = export::packets(store, view, filter);
+    let mut hashes: Vec<Integrity> = packets.iter().flat_map(|p| p.hashes()).cloned().collect();
+    hashes.sort_by_key(|hash| hash.to_string());
+    hashes.dedup();
+    let blobs = hashes
+        .iter()
+        .map(|hash| {
+            let missing = || BundleError::Missing(hash.clone());
+            let meta = store.content_meta(hash).ok_or_else(missing)?;
+            // sealed content is exported as is, it's only readable with
+            // its stack's key
+            if meta.protected_by.is_some() {
+                let sealed = store
+                    .blob_read(hash)
+                    .map_err(BundleError::Cas)?
+                    .ok_or_else(missing)?;
+                return Ok(Blob::Sealed(meta, sealed));
+            }
+            let content = store
+                .cas_read(hash)
+                .map_err(BundleError::Cas)?
+                .ok_or_else(missing)?;
+            Ok(Blob::Plain(meta.mime_type, content))
+        })
+        .collect::<Result<_, BundleError>>()?;
+
+    let manifest = Manifest {
+        format: FORMAT,
+        packets: packets.len(),
+        hashes,
+    };
+    let bundle = Bundle {
+        manifest: manifest.clone(),
+        packets,
+        blobs,
+    };
+    let encoded = bincode::serialize(&bundle).unwrap();
+    let recipient = age::scrypt::Recipient::new(SecretString::from(passphrase));
+    let encrypted = age::encrypt(&recipient, &encoded).map_err(BundleError::Encrypt)?;
+    std::fs::write(path, encrypted).map_err(BundleError::Io)?;
+    Ok(manifest)
+}
+
+/// Decrypts the bundle at `path` and checks every blob against its hash
+/// before writing anything, then inserts the packets `store` doesn't have.
+/// Returns the number inserted.
+pub fn import(store: &mut Store, path: &str, passphrase: &str) -> Result<usize, BundleError> {
+    let encrypted = std::fs::read(path).map_err(BundleError::Io)?;
+    let identity = age::scrypt::Identity::new(SecretString::from(passphrase));
+    let decrypted = age::decrypt(&identity, &encrypted).map_err(BundleError::Decrypt)?;
+    let bundle: Bundle = bincode::deserialize(&decrypted).map_err(|_| BundleError::Corrupt)?;
+
+    let manifest = &bundle.manifest;
+    if manifest.format != FORMAT {
+        return Err(BundleError::UnsupportedFormat(manifest.format));
+    }
+    if manifest.packets != bundle.packets.len() || manifest.hashes.len() != bundle.blobs.len() {
+        return Err(BundleError::Corrupt);
+    }
+    for (hash, blob) in manifest.hashes.iter().zip(&bundle.blobs) {
+        if hash.check(blob.bytes()).is_err() {
+            return Err(BundleError::HashMismatch(hash.clone()));
+        }
+        if matches!(blob, Blob::Sealed(meta, _) if meta.hash.as_ref() != Some(hash)) {
+            return Err(BundleError::Corrupt);
+        }
+    }
+
+    for blob in bundle.blobs {
+        match blob {
+            Blob::Plain(mime_type, content) => {
+                store.cas_store(&content, mime_type);
+            }
+            Blob::Sealed(meta, sealed) => store.store_sealed(&sealed, &meta),
+        }
+    }
+    let mut inserted = 0;
+    for packet in &bundle.packets {
+        match store.insert_packet(packet) {
+            Ok(_) => inserted += 1,
+            Err(InsertError::DuplicateId(_)) => (),
+        }
+    }
+    Ok(inserted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle_dir = tempfile::tempdir().unwrap();
+        let path = bundle_dir.path().join("history.age");
+        let path = path.to_str().unwrap();
+
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let mut view = View::new();
+        let stack = store
+            .add(b"stack", MimeType::TextPlain, None, None)
+            .unwrap();
+        let item = store
+            .add(b"item", MimeType::TextPlain, Some(stack.id()), None)
+            .unwrap();
+        view.merge(stack);
+        view.merge(item.clone());
+
+        let manifest = export(&store, &view, path, &ExportFilter::default(), "hunter2").unwrap();
+        assert_eq!(manifest.packets, 2);
+        assert_eq!(manifest.hashes.len(), 2);
+        // the content isn't readable without the passphrase
+        let encrypted = std::fs::read(path).unwrap();
+        assert!(!encrypted.windows(4).any(|window| window == b"item"));
+
+        let other_dir = tempfile::tempdir().unwrap();
+        let mut other = Store::new(other_dir.path().to_str().unwrap());
+        assert!(matches!(
+            import(&mut other, path, "wrong"),
+            Err(BundleError::Decrypt(_))
+        ));
+        assert_eq!(import(&mut other, path, "hunter2").unwrap(), 2);
+        assert_eq!(other.get_packet(&item.id()), Ok(Some(item.clone())));
+        assert_eq!(
+            other.cas_read(item.hash().unwrap()).unwrap().unwrap(),
+            b"item"
+        );
+
+        // importing again inserts nothing
+        assert_eq!(import(&mut other, path, "hunter2").unwrap(), 0);
+
+        // errors are returned, rather than panicking
+        let missing = bundle_dir.path().join("missing.age");
+        assert!(matches!(
+            import(&mut other, missing.to_str().unwrap(), "hunter2"),
+            Err(BundleError::Io(_))
+        ));
+        let unwritable = bundle_dir.path().join("no/such/dir.age");
+        let filter = ExportFilter::default();
+        assert!(matches!(
+            export(
+                &store,
+                &view,
+                unwritable.to_str().unwrap(),
+                &filter,
+                "hunter2"
+            ),
+            Err(BundleError::Io(_))
+        ));
+        cacache::remove_hash_sync(dir.path().join("cas"), item.hash().unwrap()).unwrap();
+        assert!(matches!(
+            export(&store, &view, path, &filter, "hunter2"),
+            Err(BundleError::Missing(_))
+        ));
+    }
+
+    #[test]
+    fn test_bundle_protected() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle_dir = tempfile::tempdir().unwrap();
+        let path = bundle_dir.path().join("history.age");
+        let path = path.to_str().unwrap();
+
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let stack = store
+            .add(b"credentials", MimeType::TextPlain, None, None)
+            .unwrap();
+        let keyring = std::collections::HashMap::from([(stack.id(), [1; 32])]);
+        store.set_keyring(Box::new(keyring.clone()));
+        store.protect_stack(stack.id()).unwrap();
+        let item = store
+            .add(b"hunter2", MimeType::TextPlain, Some(stack.id()), None)
+            .unwrap();
+        let mut view = View::new();
+        store.scan().flatten().for_each(|packet| view.merge(packet));
+
+        // sealed content is carried sealed, so it's exported without the key
+        store.set_keyring(Box::new(std::collections::HashMap::new()));
+        let filter = ExportFilter::default();
+        export(&store, &view, path, &filter, "passphrase").unwrap();
+
+        let other_dir = tempfile::tempdir().unwrap();
+        let mut other = Store::new(other_dir.path().to_str().unwrap());
+        assert_eq!(import(&mut other, path, "passphrase").unwrap(), 2);
+        let hash = item.hash().unwrap();
+        let meta = other.content_meta(hash).unwrap();
+        assert_eq!(meta.protected_by, Some(stack.id()));
+        assert_eq!(other.cas_read(hash).unwrap(), None);
+        other.set_keyring(Box::new(keyring));
+        assert_eq!(other.cas_read(hash).unwrap().unwrap(), b"hunter2");
+    }
+}
@@ -0,0 +1,295 @@
+//! Change data capture: each packet the store writes, as a line of JSON, see
+//! `wire`, to a socket, pipe or NATS subject, so other programs can react to clipboard
+//! events without linking against this crate.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::store::{Follow, Store};
+
+/// Where a `CdcSink` writes packets.
+#[derive(PartialEq, Debug, Clone)]
+pub enum CdcTarget {
+    /// A Unix domain socket something is listening on.
+    #[cfg(unix)]
+    UnixSocket(PathBuf),
+    /// A named pipe, e.g. made with `mkfifo`. Writes, and stopping the sink,
+    /// wait for a reader.
+    NamedPipe(PathBuf),
+    /// A subject on the NATS server at `address`, e.g. `127.0.0.1:4222`,
+    /// one message per packet.
+    Nats { address: String, subject: String },
+}
+
+impl std::fmt::Display for CdcTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(unix)]
+            CdcTarget::UnixSocket(path) => write!(f, "unix:{}", path.display()),
+            CdcTarget::NamedPipe(path) => write!(f, "pipe:{}", path.display()),
+            CdcTarget::Nats { address, subject } => write!(f, "nats://{}/{}", address, subject),
+        }
+    }
+}
+
+impl CdcTarget {
+    fn connect(&self) -> std::io::Result<Connection> {
+        match self {
+            #[cfg(unix)]
+            CdcTarget::UnixSocket(path) => Ok(Connection::Stream(Box::new(
+                std::os::unix::net::UnixStream::connect(path)?,
+            ))),
+            CdcTarget::NamedPipe(path) => Ok(Connection::Stream(Box::new(
+                std::fs::OpenOptions::new().write(true).open(path)?,
+            ))),
+            CdcTarget::Nats { address, subject } => {
+                let mut stream = TcpStream::connect(address)?;
+                // the server greets with INFO before anything else
+                let mut info = String::new();
+                BufReader::new(&stream).read_line(&mut info)?;
+                if !info.starts_with("INFO") {
+                    return Err(std::io::Error::other(format!(
+                        "not a NATS server: {}",
+                        info
+                    )));
+                }
+                stream.write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n")?;
+                Ok(Connection::Nats {
+                    stream,
+                    subject: subject.clone(),
+                    incoming: Vec::new(),
+                })
+            }
+        }
+    }
+}
+
+enum Connection {
+    Stream(Box<dyn Write + Send>),
+    Nats {
+        stream: TcpStream,
+        subject: String,
+        /// What the server sent that hasn't made a whole line yet.
+        incoming: Vec<u8>,
+    },
+}
+
+impl Connection {
+    fn send(&mut self, json: &[u8]) -> std::io::Result<()> {
+        match self {
+            Connection::Stream(writer) => {
+                writer.write_all(json)?;
+                writer.write_all(b"\n")?;
+                writer.flush()
+            }
+            Connection::Nats {
+                stream, subject, ..
+            } => {
+                let mut message = format!("PUB {} {}\r\n", subject, json.len()).into_bytes();
+                message.extend_from_slice(json);
+                message.extend_from_slice(b"\r\n");
+                stream.write_all(&message)
+            }
+        }
+    }
+
+    /// Answers the pings a NATS server drops idle clients without.
+    fn keep_alive(&mut self) -> std::io::Result<()> {
+        let Connection::Nats {
+            stream, incoming, ..
+        } = self
+        else {
+            return Ok(());
+        };
+        stream.set_nonblocking(true)?;
+        let mut buf = [0; 512];
+        let read = loop {
+            match stream.read(&mut buf) {
+                Ok(0) => break Err(std::io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => incoming.extend_from_slice(&buf[..len]),
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+        stream.set_nonblocking(false)?;
+        read?;
+        while let Some(end) = incoming.windows(2).position(|window| window == b"\r\n") {
+            let line: Vec<u8> = incoming.drain(..end + 2).collect();
+            if line.starts_with(b"PING") {
+                stream.write_all(b"PONG\r\n")?;
+            } else if line.starts_with(b"-ERR") {
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+                return Err(std::io::Error::other(line));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes each packet in the log, then each packet as it's written, to a
+/// `CdcTarget` from a thread of its own, until it's stopped or dropped.
+///
+/// It's a consumer, see `Store::register_consumer`, named after its target,
+/// e.g. `cdc:unix:/run/s2.sock`: a packet is acked once it's written, so a
+/// sink started again resumes where the last left off. Delivery is at least
+/// once. While the target is unreachable, the sink retries every second.
+/// Stop it before dropping the store, as it holds the store's log open.
+pub struct CdcSink {
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl CdcSink {
+    pub fn start(store: &Store, target: CdcTarget) -> CdcSink {
+        let cursor = store.register_consumer(&format!("cdc:{}", target));
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let worker = std::thread::spawn(move || {
+            // followed only while connected: a packet that wasn't written
+            // isn't acked, so following from the cursor again redelivers it
+            let mut connection: Option<(Connection, Follow)> = None;
+            while !stopped.load(Ordering::Relaxed) {
+                let Some((conn, follow)) = connection.as_mut() else {
+                    match target.connect() {
+                        Ok(conn) => connection = Some((conn, cursor.follow())),
+                        Err(_) => std::thread::sleep(Duration::from_secs(1)),
+                    }
+                    continue;
+                };
+                let packet = match follow.next_timeout(Duration::from_millis(100)) {
+                    Some(Ok(packet)) => packet,
+                    // passed over, like `Store::consume` does
+                    Some(Err(err)) => {
+                        cursor.ack(err.id);
+                        continue;
+                    }
+                    None => {
+                        if conn.keep_alive().is_err() {
+                            connection = None;
+                        }
+                        continue;
+                    }
+                };
+                let json = crate::wire::to_json(&packet);
+                match conn.send(json.as_bytes()) {
+                    Ok(()) => cursor.ack(packet.id()),
+                    Err(_) => connection = None,
+                }
+            }
+        });
+        CdcSink {
+            stop,
+            worker: Some(worker),
+        }
+    }
+
+    /// Stops the sink, waiting for the packet being written, if any.
+    pub fn stop(mut self) {
+        self.shut_down();
+    }
+
+    fn shut_down(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            // a panic on the worker was reported as it happened, and
+            // re-raising it here would be a panic in `drop`
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for CdcSink {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    use tempfile::tempdir;
+
+    use crate::store::MimeType;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        let dir = tempdir().unwrap();
+        let mut store = Store::new(dir.path().join("store").to_str().unwrap());
+        let first = store
+            .add(b"first", MimeType::TextPlain, None, None)
+            .unwrap();
+
+        let socket = dir.path().join("cdc.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        let sink = CdcSink::start(&store, CdcTarget::UnixSocket(socket.clone()));
+        let (stream, _) = listener.accept().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let mut next = || crate::wire::from_json(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(next(), first);
+        let second = store
+            .add(b"second", MimeType::TextPlain, None, None)
+            .unwrap();
+        assert_eq!(next(), second);
+        sink.stop();
+
+        let consumer = format!("cdc:unix:{}", socket.display());
+        assert_eq!(store.cursor(&consumer), Some(second.id()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unreachable() {
+        let dir = tempdir().unwrap();
+        let mut store = Store::new(dir.path().join("store").to_str().unwrap());
+        let socket = dir.path().join("cdc.sock");
+        let sink = CdcSink::start(&store, CdcTarget::UnixSocket(socket.clone()));
+        let first = store
+            .add(b"first", MimeType::TextPlain, None, None)
+            .unwrap();
+
+        // what's written while it's retrying is sent once it connects
+        std::thread::sleep(Duration::from_millis(100));
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let next = crate::wire::from_json(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(next, first);
+        sink.stop();
+    }
+
+    #[test]
+    fn test_nats() {
+        let dir = tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let packet = store
+            .add(b"hello", MimeType::TextPlain, None, None)
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = CdcTarget::Nats {
+            address: listener.local_addr().unwrap().to_string(),
+            subject: "clipboard.packets".to_string(),
+        };
+        let sink = CdcSink::start(&store, target);
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"INFO {}\r\n").unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert!(lines.next().unwrap().unwrap().starts_with("CONNECT "));
+        let json = crate::wire::to_json(&packet);
+        assert_eq!(
+            lines.next().unwrap().unwrap(),
+            format!("PUB clipboard.packets {}", json.len())
+        );
+        assert_eq!(lines.next().unwrap().unwrap(), json);
+        sink.stop();
+    }
+}
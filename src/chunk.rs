@@ -0,0 +1,79 @@
+//! Content-defined chunking: boundaries depend on the bytes around them
+//! rather than their offset, so an insertion only changes the chunks near
+//! it and similar content shares most of its chunks.
+
+/// Random values for each byte, generated with splitmix64 so they're the same
+/// everywhere chunks are compared.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Splits `content` into chunks averaging about `avg_size` bytes, and
+/// between a quarter and four times that.
+pub(crate) fn chunks(content: &[u8], avg_size: usize) -> Vec<&[u8]> {
+    let mask = (avg_size.next_power_of_two() as u64) - 1;
+    let min_size = (avg_size / 4).max(1);
+    let max_size = avg_size * 4;
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut hash: u64 = 0;
+    for (i, &byte) in content.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+        let len = i + 1 - start;
+        if len >= min_size && (hash & mask == 0 || len >= max_size) {
+            chunks.push(&content[start..=i]);
+            start = i + 1;
+            hash = 0;
+        }
+    }
+    if start < content.len() {
+        chunks.push(&content[start..]);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks() {
+        // deterministic pseudo-random content, from xorshift
+        let mut state: u64 = 88172645463325252;
+        let content: Vec<u8> = (0..200_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+
+        let original = chunks(&content, 4096);
+        assert_eq!(original.concat(), content);
+        assert!(original.len() > 10);
+        assert!(original.iter().all(|chunk| chunk.len() <= 4096 * 4));
+
+        // an insertion near the start leaves most chunks intact
+        let mut edited = content.clone();
+        edited.splice(1000..1000, b"inserted".iter().copied());
+        let edited = chunks(&edited, 4096);
+        let shared = edited
+            .iter()
+            .filter(|chunk| original.contains(chunk))
+            .count();
+        assert!(shared >= original.len() - 3);
+    }
+}
@@ -0,0 +1,124 @@
+//! Assembles LLM context from the children of a stack.
+
+use scru128::Scru128Id;
+
+use crate::store::{MimeType, Store};
+use crate::view::{BudgetMeasure, BudgetOrder, View};
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ContextOptions {
+    pub max_tiktokens: usize,
+    /// Which children win when the stack doesn't fit in the budget.
+    pub order: BudgetOrder,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Message {
+    pub id: Scru128Id,
+    pub mime_type: MimeType,
+    pub content: String,
+}
+
+impl Message {
+    pub fn header(&self) -> String {
+        format!("--- {} ---", self.id)
+    }
+}
+
+/// The text children of `stack_id` that fit in the budget, oldest first.
+/// Binary content is skipped and doesn't count towards the budget.
+pub fn messages(
+    store: &Store,
+    view: &View,
+    stack_id: Scru128Id,
+    options: &ContextOptions,
+) -> Vec<Message> {
+    let (mut selected, _) = view.select_within_budget_by(
+        store,
+        stack_id,
+        options.max_tiktokens,
+        BudgetMeasure::Tiktokens,
+        options.order,
+        |meta| meta.mime_type.is_text(),
+    );
+    selected.sort_by_key(|id| view.items.get(id).map(|item| item.last_touched));
+
+    selected
+        .into_iter()
+        .filter_map(|id| {
+            let item = view.items.get(&id)?;
+            let meta = store.content_meta(&item.hash)?;
+            let content = store.cas_read(&item.hash).ok().flatten()?;
+            Some(Message {
+                id,
+                mime_type: meta.mime_type,
+                content: String::from_utf8_lossy(&content).into_owned(),
+            })
+        })
+        .collect()
+}
+
+/// The messages for `stack_id` concatenated into a single prompt, each
+/// preceded by a header line.
+pub fn prompt(store: &Store, view: &View, stack_id: Scru128Id, options: &ContextOptions) -> String {
+    messages(store, view, stack_id, options)
+        .iter()
+        .map(|message| format!("{}\n{}\n", message.header(), message.content))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let mut store = Store::new(path);
+
+        let stack_id = store
+            .add(b"Stack", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        let id_1 = store
+            .add(b"first", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+        store
+            .add(b"\x89PNG", MimeType::ImagePng, Some(stack_id), None)
+            .unwrap();
+        let id_3 = store
+            .add(b"third", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+
+        let options = ContextOptions {
+            max_tiktokens: 10,
+            order: BudgetOrder::MostRecent,
+        };
+        assert_eq!(
+            prompt(&store, &view, stack_id, &options),
+            format!("--- {} ---\nfirst\n\n--- {} ---\nthird\n", id_1, id_3)
+        );
+
+        let options = ContextOptions {
+            max_tiktokens: 5,
+            ..options
+        };
+        let messages = messages(&store, &view, stack_id, &options);
+        assert_eq!(
+            messages,
+            vec![Message {
+                id: id_3,
+                mime_type: MimeType::TextPlain,
+                content: "third".into(),
+            }]
+        );
+    }
+}
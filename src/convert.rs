@@ -0,0 +1,82 @@
+//! Converts stored images between formats, see `Store::cas_read_as`.
+
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat};
+
+use crate::store::{CasError, MimeType};
+
+#[derive(Debug)]
+pub enum ConvertError {
+    Cas(CasError),
+    /// Only PNG, JPEG and WebP images convert.
+    Unsupported {
+        from: MimeType,
+        to: MimeType,
+    },
+    Image(image::ImageError),
+}
+
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::Cas(err) => err.fmt(f),
+            ConvertError::Unsupported { from, to } => {
+                write!(f, "can't convert {} to {}", from.as_str(), to.as_str())
+            }
+            ConvertError::Image(err) => write!(f, "couldn't convert image: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+pub(crate) fn image_format(mime_type: &MimeType) -> Option<ImageFormat> {
+    match mime_type.as_str() {
+        "image/png" => Some(ImageFormat::Png),
+        "image/jpeg" => Some(ImageFormat::Jpeg),
+        "image/webp" => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+pub(crate) fn convert(
+    content: &[u8],
+    from: ImageFormat,
+    to: ImageFormat,
+) -> Result<Vec<u8>, image::ImageError> {
+    let image = image::load_from_memory_with_format(content, from)?;
+    // JPEG has no alpha channel
+    let image = match to {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => image,
+    };
+    let mut converted = Cursor::new(Vec::new());
+    image.write_to(&mut converted, to)?;
+    Ok(converted.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            4,
+            3,
+            image::Rgba([255, 0, 0, 128]),
+        ));
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+
+        for to in [ImageFormat::Jpeg, ImageFormat::WebP] {
+            let converted = convert(&png, ImageFormat::Png, to).unwrap();
+            assert_eq!(image::guess_format(&converted).unwrap(), to);
+            let decoded = image::load_from_memory(&converted).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (4, 3));
+        }
+        assert!(convert(b"not a png", ImageFormat::Png, ImageFormat::Jpeg).is_err());
+    }
+}
@@ -0,0 +1,41 @@
+//! Content made from other content: conversions, thumbnails, OCR text, link
+//! previews. Each is recorded against its source's hash, see
+//! `Store::put_derived`, and removed along with the source.
+
+use serde::{Deserialize, Serialize};
+use ssri::Integrity;
+
+use crate::store::MimeType;
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub enum DerivedKind {
+    /// An image as it was before its metadata was stripped, see
+    /// `ExifPolicy::StripKeepOriginal`.
+    Original,
+    /// The source converted to another type, see `Store::cas_read_as`.
+    Conversion(MimeType),
+    LinkPreview,
+    /// The favicon of a link preview's page.
+    Favicon,
+    Thumbnail,
+    /// Text recognised in an image.
+    OcrText,
+    Other(String),
+}
+
+/// Keys start with the source's hash, so a prefix scan finds everything
+/// derived from it.
+pub(crate) fn key(source: &Integrity, kind: &DerivedKind) -> Vec<u8> {
+    let mut key = prefix(source);
+    key.extend(bincode::serialize(kind).unwrap());
+    key
+}
+
+pub(crate) fn prefix(source: &Integrity) -> Vec<u8> {
+    bincode::serialize(source).unwrap()
+}
+
+/// The kind from a key made by `key`.
+pub(crate) fn kind(source: &Integrity, key: &[u8]) -> Option<DerivedKind> {
+    bincode::deserialize(&key[prefix(source).len()..]).ok()
+}
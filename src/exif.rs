@@ -0,0 +1,134 @@
+//! Strips EXIF metadata, which can hold GPS coordinates, camera serial
+//! numbers and the like, from JPEG and PNG images.
+
+use crate::store::MimeType;
+
+/// What `Store::add`, `update`, `fork` and `cas_write` do with metadata in
+/// JPEG and PNG images.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum ExifPolicy {
+    #[default]
+    Keep,
+    Strip,
+    /// Strip it, keeping the original for `Store::original`. Originals of
+    /// protected content aren't kept, as they'd be stored unsealed.
+    StripKeepOriginal,
+}
+
+const JPEG_EXIF: &[u8] = b"Exif\0\0";
+const JPEG_XMP: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const PNG_XMP: &[u8] = b"XML:com.adobe.xmp\0";
+
+/// `content` without its EXIF and XMP metadata, or `None` if it has none or
+/// isn't a well-formed JPEG or PNG.
+pub(crate) fn strip(content: &[u8], mime_type: &MimeType) -> Option<Vec<u8>> {
+    match mime_type.as_str() {
+        "image/jpeg" => strip_jpeg(content),
+        "image/png" => strip_png(content),
+        _ => None,
+    }
+}
+
+fn strip_jpeg(content: &[u8]) -> Option<Vec<u8>> {
+    if !content.starts_with(b"\xff\xd8") {
+        return None;
+    }
+    let mut stripped = content[..2].to_vec();
+    let mut i = 2;
+    loop {
+        let marker = *content.get(i + 1)?;
+        if content[i] != 0xff {
+            return None;
+        }
+        match marker {
+            // start of scan: the rest is image data
+            0xda | 0xd9 => break,
+            0x01 | 0xd0..=0xd7 => {
+                stripped.extend_from_slice(&content[i..i + 2]);
+                i += 2;
+                continue;
+            }
+            _ => (),
+        }
+        let len = u16::from_be_bytes(content.get(i + 2..i + 4)?.try_into().unwrap()) as usize;
+        if len < 2 {
+            return None;
+        }
+        let segment = content.get(i..i + 2 + len)?;
+        let payload = &segment[4..];
+        if !(marker == 0xe1 && (payload.starts_with(JPEG_EXIF) || payload.starts_with(JPEG_XMP))) {
+            stripped.extend_from_slice(segment);
+        }
+        i += 2 + len;
+    }
+    stripped.extend_from_slice(&content[i..]);
+    (stripped.len() < content.len()).then_some(stripped)
+}
+
+fn strip_png(content: &[u8]) -> Option<Vec<u8>> {
+    if !content.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+    let mut stripped = PNG_SIGNATURE.to_vec();
+    let mut i = PNG_SIGNATURE.len();
+    while i < content.len() {
+        let len = u32::from_be_bytes(content.get(i..i + 4)?.try_into().unwrap()) as usize;
+        // length, type, data and CRC
+        let chunk = content.get(i..i + 12 + len)?;
+        let kind = &chunk[4..8];
+        let data = &chunk[8..8 + len];
+        if !(kind == b"eXIf" || (kind == b"iTXt" && data.starts_with(PNG_XMP))) {
+            stripped.extend_from_slice(chunk);
+        }
+        i += chunk.len();
+    }
+    (stripped.len() < content.len()).then_some(stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip() {
+        let jpeg = [
+            b"\xff\xd8".as_slice(),
+            b"\xff\xe0\x00\x04JF",
+            b"\xff\xe1\x00\x0cExif\0\0GPS!",
+            b"\xff\xda\x00\x02\xff\xe1image data",
+            b"\xff\xd9",
+        ]
+        .concat();
+        let stripped = strip(&jpeg, &MimeType::from("image/jpeg")).unwrap();
+        assert_eq!(
+            stripped,
+            b"\xff\xd8\xff\xe0\x00\x04JF\xff\xda\x00\x02\xff\xe1image data\xff\xd9"
+        );
+        assert_eq!(strip(&stripped, &MimeType::from("image/jpeg")), None);
+
+        let chunk = |kind: &[u8], data: &[u8]| {
+            [&(data.len() as u32).to_be_bytes(), kind, data, b"crc!"].concat()
+        };
+        let png = [
+            PNG_SIGNATURE.to_vec(),
+            chunk(b"IHDR", b"header"),
+            chunk(b"eXIf", b"MM\0*GPS"),
+            chunk(b"IEND", b""),
+        ]
+        .concat();
+        let stripped = strip(&png, &MimeType::ImagePng).unwrap();
+        assert_eq!(
+            stripped,
+            [
+                PNG_SIGNATURE.to_vec(),
+                chunk(b"IHDR", b"header"),
+                chunk(b"IEND", b"")
+            ]
+            .concat()
+        );
+
+        // truncated images are left alone
+        assert_eq!(strip(&png[..20], &MimeType::ImagePng), None);
+    }
+}
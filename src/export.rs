@@ -0,0 +1,336 @@
+//! Exports a selection of a store, e.g. one stack to share with a colleague.
+
+use std::collections::HashSet;
+
+use scru128::Scru128Id;
+
+use crate::store::{AddPacket, MimeType, Packet, Store};
+use crate::view::{Item, View};
+
+/// Selects the items an export includes. Unset fields match everything.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Matches these stacks and everything beneath them. Children a stack
+    /// only shares through a fork stay with their original stack.
+    pub stack_ids: Option<Vec<Scru128Id>>,
+    /// Inclusive lower bound on item ids, i.e. creation time.
+    pub since: Option<Scru128Id>,
+    /// Exclusive upper bound on item ids.
+    pub until: Option<Scru128Id>,
+    pub mime_types: Option<Vec<MimeType>>,
+}
+
+/// The items of `view` matching `filter`, plus the stacks above them so they
+/// keep their place, ordered by id. The stacks named in the filter are always
+/// included.
+pub fn select<'a>(store: &Store, view: &'a View, filter: &ExportFilter) -> Vec<&'a Item> {
+    let in_scope: Box<dyn Fn(&Scru128Id) -> bool> = match &filter.stack_ids {
+        Some(stack_ids) => {
+            let mut scope = HashSet::new();
+            let mut pending = stack_ids.clone();
+            while let Some(id) = pending.pop() {
+                if let Some(item) = view.get(&id).filter(|_| scope.insert(id)) {
+                    pending.extend(&item.children);
+                }
+            }
+            Box::new(move |id| scope.contains(id))
+        }
+        None => Box::new(|_| true),
+    };
+    let named = |id: &Scru128Id| {
+        filter
+            .stack_ids
+            .as_ref()
+            .is_some_and(|stack_ids| stack_ids.contains(id))
+    };
+
+    let mut selected = HashSet::new();
+    for item in view.items.values().filter(|item| in_scope(&item.id)) {
+        let matches = filter.since.is_none_or(|since| item.id >= since)
+            && filter.until.is_none_or(|until| item.id < until)
+            && filter.mime_types.as_ref().is_none_or(|mime_types| {
+                store
+                    .content_meta(&item.hash)
+                    .is_some_and(|meta| mime_types.contains(&meta.mime_type))
+            });
+        if !matches && !named(&item.id) {
+            continue;
+        }
+
+        let mut id = Some(item.id);
+        while let Some(item) = id.and_then(|id| view.get(&id)) {
+            if !selected.insert(item.id) {
+                break;
+            }
+            id = item.stack_id;
+        }
+    }
+
+    let mut items: Vec<&Item> = selected.iter().filter_map(|id| view.get(id)).collect();
+    items.sort_by_key(|item| item.id);
+    items
+}
+
+/// The packets that rebuild the selected items, in log order. Forks of items
+/// outside the selection become Adds, so no unselected content is needed.
+/// Conflict policies are kept, so the updates resolve as they do here.
+/// Packets that don't decode aren't in the view either, so are left out.
+pub fn packets(store: &Store, view: &View, filter: &ExportFilter) -> Vec<Packet> {
+    let selected: HashSet<Scru128Id> = select(store, view, filter)
+        .iter()
+        .map(|item| item.id)
+        .collect();
+
+    store
+        .scan()
+        .flatten()
+        .filter_map(|packet| match packet {
+            Packet::Add(_) | Packet::Snapshot(_) => {
+                Some(packet).filter(|packet| selected.contains(&packet.id()))
+            }
+            Packet::Update(ref update) if selected.contains(&update.source_id) => Some(packet),
+            Packet::Delete(ref delete) if selected.contains(&delete.source_id) => Some(packet),
+            Packet::Access(ref access) if selected.contains(&access.source_id) => Some(packet),
+            Packet::Policy(_) => Some(packet),
+            Packet::Fork(fork) if selected.contains(&fork.id) => {
+                if selected.contains(&fork.source_id) {
+                    return Some(Packet::Fork(fork));
+                }
+                // later packets bring the item up to date
+                let item = view.get(&fork.id)?;
+                let renditions = match fork.hash {
+                    Some(_) => Vec::new(),
+                    None => item.renditions.clone(),
+                };
+                Some(Packet::Add(AddPacket {
+                    id: fork.id,
+                    hash: fork.hash.unwrap_or_else(|| item.hash.clone()),
+                    stack_id: fork.stack_id.or(item.stack_id),
+                    source: fork.source,
+                    device_id: fork.device_id,
+                    clock: fork.clock,
+                    renditions,
+                }))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Writes the selected packets and their content to a new store at `path`,
+/// which the recipient can `Store::absorb`. Returns the number of packets
+/// written.
+pub fn to_store(store: &Store, view: &View, path: &str, filter: &ExportFilter) -> usize {
+    let mut target = Store::new(path);
+    packets(store, view, filter)
+        .iter()
+        .filter(|packet| target.copy_packet(store, packet).is_ok())
+        .count()
+}
+
+/// The selected packets as JSON lines, see `wire`, for tools that don't read
+/// stores.
+pub fn to_json_lines(store: &Store, view: &View, filter: &ExportFilter) -> String {
+    let packets = packets(store, view, filter);
+    packets
+        .iter()
+        .map(|packet| crate::wire::to_json(packet) + "\n")
+        .collect()
+}
+
+/// A Markdown document of the stack `stack_id`: its content as the title,
+/// then each child in the view's child order. Text is inline, PNGs are
+/// embedded as data URIs, other content is named by its terse preview, and
+/// children that are stacks become sections of their own.
+pub fn to_markdown(view: &View, store: &Store, stack_id: Scru128Id) -> String {
+    let mut markdown = String::new();
+    if let Some(stack) = view.get(&stack_id) {
+        write_markdown(view, store, stack, 1, &mut markdown);
+    }
+    markdown
+}
+
+fn write_markdown(view: &View, store: &Store, stack: &Item, depth: usize, out: &mut String) {
+    let title = store
+        .cas_read(&stack.hash)
+        .ok()
+        .flatten()
+        .map(|content| String::from_utf8_lossy(&content).trim().to_string())
+        .unwrap_or_default();
+    out.push_str(&format!("{} {}\n\n", "#".repeat(depth.min(6)), title));
+
+    for child in view.iter_children(stack) {
+        if !child.children.is_empty() {
+            write_markdown(view, store, child, depth + 1, out);
+            continue;
+        }
+        let Some(meta) = store.content_meta(&child.hash) else {
+            continue;
+        };
+        match store.cas_read(&child.hash).ok().flatten() {
+            Some(content) if meta.mime_type.is_text() => {
+                out.push_str(String::from_utf8_lossy(&content).trim_end());
+            }
+            Some(content) if meta.mime_type == MimeType::ImagePng => {
+                out.push_str(&format!(
+                    "![{}](data:image/png;base64,{})",
+                    meta.terse,
+                    base64(&content)
+                ));
+            }
+            _ => out.push_str(&format!("*{}*", meta.terse)),
+        }
+        out.push_str("\n\n");
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let export_dir = tempfile::tempdir().unwrap();
+
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let mut view = View::new();
+        let mut add = |store: &mut Store, content: &[u8], stack_id| {
+            let packet = store
+                .add(content, MimeType::TextPlain, stack_id, None)
+                .unwrap();
+            view.merge(packet.clone());
+            packet.id()
+        };
+
+        let work = add(&mut store, b"work", None);
+        let notes = add(&mut store, b"notes", Some(work));
+        let todo = add(&mut store, b"todo", Some(notes));
+        let personal = add(&mut store, b"personal", None);
+        add(&mut store, b"secret", Some(personal));
+        let packet = store
+            .fork(personal, None, MimeType::TextPlain, Some(notes), None)
+            .unwrap();
+        let forked = packet.id();
+        view.merge(packet);
+        let packet = store
+            .update(todo, Some(b"todo v2"), MimeType::TextPlain, None, None)
+            .unwrap();
+        view.merge(packet);
+
+        let filter = ExportFilter {
+            stack_ids: Some(vec![notes]),
+            ..Default::default()
+        };
+        let ids: Vec<_> = select(&store, &view, &filter)
+            .iter()
+            .map(|item| item.id)
+            .collect();
+        // notes' parent stack comes along, personal doesn't
+        assert_eq!(ids, vec![work, notes, todo, forked]);
+
+        let path = export_dir.path().to_str().unwrap();
+        assert_eq!(to_store(&store, &view, path, &filter), 5);
+        let lines = to_json_lines(&store, &view, &filter);
+        let packets: Vec<_> = lines
+            .lines()
+            .map(|line| crate::wire::from_json(line).unwrap())
+            .collect();
+        assert_eq!(packets, super::packets(&store, &view, &filter));
+
+        let exported = Store::new(path);
+        let mut exported_view = View::new();
+        exported
+            .scan()
+            .for_each(|p| exported_view.merge(p.unwrap()));
+        assert_eq!(exported_view.items.len(), 4);
+        assert_eq!(
+            exported
+                .cas_read(&exported_view.items[&todo].hash)
+                .unwrap()
+                .unwrap(),
+            b"todo v2".to_vec()
+        );
+        assert_eq!(exported_view.items[&forked].stack_id, Some(notes));
+        assert_eq!(
+            exported
+                .cas_read(&exported_view.items[&forked].hash)
+                .unwrap()
+                .unwrap(),
+            b"personal".to_vec()
+        );
+
+        // a date range narrows the selection, keeping the stacks above
+        let filter = ExportFilter {
+            since: Some(todo),
+            until: Some(personal),
+            ..Default::default()
+        };
+        let ids: Vec<_> = select(&store, &view, &filter)
+            .iter()
+            .map(|item| item.id)
+            .collect();
+        assert_eq!(ids, vec![work, notes, todo]);
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let mut view = View::new();
+        let mut add = |store: &mut Store, content: &[u8], mime_type, stack_id| {
+            let packet = store.add(content, mime_type, stack_id, None).unwrap();
+            view.merge(packet.clone());
+            packet.id()
+        };
+
+        let research = add(&mut store, b"Research", MimeType::TextPlain, None);
+        add(
+            &mut store,
+            b"first note\n",
+            MimeType::TextPlain,
+            Some(research),
+        );
+        let sources = add(&mut store, b"Sources", MimeType::TextPlain, Some(research));
+        add(
+            &mut store,
+            b"https://example.com",
+            MimeType::TextPlain,
+            Some(sources),
+        );
+        add(&mut store, b"Man", MimeType::ImagePng, Some(research));
+        let pdf = MimeType::Other("application/pdf".into());
+        add(&mut store, b"%PDF", pdf, Some(research));
+
+        assert_eq!(
+            to_markdown(&view, &store, research),
+            "# Research\n\n\
+             first note\n\n\
+             ## Sources\n\n\
+             https://example.com\n\n\
+             ![PNG · 3 B](data:image/png;base64,TWFu)\n\n\
+             *application/pdf · 4 B*\n\n"
+        );
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+    }
+}
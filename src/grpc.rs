@@ -0,0 +1,333 @@
+//! A gRPC service, see `proto/s2.proto`, so clients in any language can
+//! drive a store running as a daemon.
+
+// `Status` is what tonic handlers return, however large
+#![allow(clippy::result_large_err)]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use scru128::Scru128Id;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use crate::source::Source;
+use crate::stacks::Stacks;
+use crate::store::{MimeType, Packet, PacketKind};
+
+/// The messages and the generated client and server.
+pub mod proto {
+    tonic::include_proto!("s2");
+}
+
+/// Serves a store, through `Stacks`, so searches see every packet written.
+#[derive(Clone)]
+pub struct StoreService {
+    stacks: Arc<Mutex<Stacks>>,
+}
+
+impl StoreService {
+    pub fn new(stacks: Stacks) -> Self {
+        StoreService {
+            stacks: Arc::new(Mutex::new(stacks)),
+        }
+    }
+
+    /// The service, to add to a `tonic::transport::Server`.
+    pub fn into_server(self) -> proto::store_server::StoreServer<Self> {
+        proto::store_server::StoreServer::new(self)
+    }
+}
+
+fn parse_id(id: &str) -> Result<Scru128Id, Status> {
+    id.parse()
+        .map_err(|_| Status::invalid_argument(format!("invalid id: {}", id)))
+}
+
+fn parse_optional_id(id: Option<&String>) -> Result<Option<Scru128Id>, Status> {
+    id.map(|id| parse_id(id)).transpose()
+}
+
+impl From<&Packet> for proto::Packet {
+    fn from(packet: &Packet) -> Self {
+        let (source_id, stack_id) = match packet {
+            Packet::Add(packet) => (None, packet.stack_id),
+            Packet::Snapshot(packet) => (None, packet.stack_id),
+            Packet::Update(packet) => (Some(packet.source_id), packet.stack_id),
+            Packet::Fork(packet) => (Some(packet.source_id), packet.stack_id),
+            Packet::Delete(packet) => (Some(packet.source_id), None),
+            Packet::Access(packet) => (Some(packet.source_id), None),
+            Packet::Policy(_) => (None, None),
+        };
+        let kind = match packet.kind() {
+            PacketKind::Add => proto::PacketKind::Add,
+            PacketKind::Update => proto::PacketKind::Update,
+            PacketKind::Fork => proto::PacketKind::Fork,
+            PacketKind::Delete => proto::PacketKind::Delete,
+            PacketKind::Snapshot => proto::PacketKind::Snapshot,
+            PacketKind::Access => proto::PacketKind::Access,
+            PacketKind::Policy => proto::PacketKind::Policy,
+        };
+        proto::Packet {
+            id: packet.id().to_string(),
+            kind: kind.into(),
+            source_id: source_id.map(|id| id.to_string()),
+            hash: packet.hash().map(|hash| hash.to_string()),
+            stack_id: stack_id.map(|id| id.to_string()),
+            source: packet.source().map(|source| source.app.clone()),
+            device_id: packet.device_id().map(str::to_string),
+        }
+    }
+}
+
+impl StoreService {
+    /// Runs `f` with the stacks on tokio's blocking pool, as it waits on the
+    /// lock and reads and writes the store.
+    async fn with_stacks<T, F>(&self, f: F) -> Result<T, Status>
+    where
+        F: FnOnce(&mut Stacks) -> Result<T, Status> + Send + 'static,
+        T: Send + 'static,
+    {
+        let stacks = self.stacks.clone();
+        tokio::task::spawn_blocking(move || f(&mut stacks.lock().unwrap()))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?
+    }
+}
+
+/// Updates or forks an item, which has to be in the view.
+fn change(
+    stacks: &mut Stacks,
+    request: proto::ChangeRequest,
+    fork: bool,
+) -> Result<Response<proto::Packet>, Status> {
+    let id = parse_id(&request.id)?;
+    let stack_id = parse_optional_id(request.stack_id.as_ref())?;
+    if stacks.view().get(&id).is_none() {
+        return Err(Status::not_found(format!("no item {}", id)));
+    }
+    if let Some(stack_id) = stack_id.filter(|id| stacks.view().get(id).is_none()) {
+        return Err(Status::not_found(format!("no stack {}", stack_id)));
+    }
+    let content = request.content.as_deref();
+    let mime_type = MimeType::from(request.mime_type.as_str());
+    let source = request.source.map(Source::new);
+    let packet = match fork {
+        true => stacks.fork(id, content, mime_type, stack_id, source),
+        false => stacks.update(id, content, mime_type, stack_id, source),
+    }
+    .map_err(|err| Status::failed_precondition(err.to_string()))?;
+    Ok(Response::new((&packet).into()))
+}
+
+#[tonic::async_trait]
+impl proto::store_server::Store for StoreService {
+    async fn add(
+        &self,
+        request: Request<proto::AddRequest>,
+    ) -> Result<Response<proto::Packet>, Status> {
+        let request = request.into_inner();
+        let stack_id = parse_optional_id(request.stack_id.as_ref())?;
+        let mime_type = MimeType::from(request.mime_type.as_str());
+        let source = request.source.map(Source::new);
+        self.with_stacks(move |stacks| {
+            let packet = stacks
+                .add(&request.content, mime_type, stack_id, source)
+                .map_err(|err| Status::failed_precondition(err.to_string()))?;
+            Ok(Response::new((&packet).into()))
+        })
+        .await
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::ChangeRequest>,
+    ) -> Result<Response<proto::Packet>, Status> {
+        let request = request.into_inner();
+        self.with_stacks(move |stacks| change(stacks, request, false))
+            .await
+    }
+
+    async fn fork(
+        &self,
+        request: Request<proto::ChangeRequest>,
+    ) -> Result<Response<proto::Packet>, Status> {
+        let request = request.into_inner();
+        self.with_stacks(move |stacks| change(stacks, request, true))
+            .await
+    }
+
+    async fn delete(
+        &self,
+        request: Request<proto::DeleteRequest>,
+    ) -> Result<Response<proto::Packet>, Status> {
+        let id = parse_id(&request.into_inner().id)?;
+        self.with_stacks(move |stacks| {
+            if stacks.view().get(&id).is_none() {
+                return Err(Status::not_found(format!("no item {}", id)));
+            }
+            let packet = stacks
+                .delete(id)
+                .map_err(|err| Status::failed_precondition(err.to_string()))?;
+            Ok(Response::new((&packet).into()))
+        })
+        .await
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SearchReply>, Status> {
+        let request = request.into_inner();
+        self.with_stacks(move |stacks| {
+            let hits = stacks
+                .search(&request.query)
+                .into_iter()
+                .map(|(score, item)| proto::SearchHit {
+                    score,
+                    id: item.id.to_string(),
+                    hash: item.hash.to_string(),
+                    stack_id: item.stack_id.map(|id| id.to_string()),
+                })
+                .collect();
+            Ok(Response::new(proto::SearchReply { hits }))
+        })
+        .await
+    }
+
+    async fn get_content(
+        &self,
+        request: Request<proto::GetContentRequest>,
+    ) -> Result<Response<proto::Content>, Status> {
+        let id = parse_id(&request.into_inner().id)?;
+        self.with_stacks(move |stacks| {
+            let not_found = || Status::not_found(format!("no content for {}", id));
+            let item = stacks.view().get(&id).ok_or_else(not_found)?;
+            let content = stacks
+                .store()
+                .cas_read(&item.hash)
+                .map_err(|err| Status::data_loss(err.to_string()))?
+                .ok_or_else(not_found)?;
+            let mime_type = stacks
+                .store()
+                .content_meta(&item.hash)
+                .map(|meta| meta.mime_type)
+                .unwrap_or(MimeType::TextPlain);
+            Ok(Response::new(proto::Content {
+                content,
+                mime_type: mime_type.as_str().to_string(),
+            }))
+        })
+        .await
+    }
+
+    type WatchPacketsStream = ReceiverStream<Result<proto::Packet, Status>>;
+
+    /// Follows the log on a thread of its own, see `Store::follow`, until
+    /// the client goes away. A client that falls 64 packets behind is
+    /// disconnected, to watch again from the last packet it saw.
+    async fn watch_packets(
+        &self,
+        request: Request<proto::WatchRequest>,
+    ) -> Result<Response<Self::WatchPacketsStream>, Status> {
+        let after = parse_optional_id(request.into_inner().after.as_ref())?;
+        let mut follow = self.stacks.lock().unwrap().store().follow_after(after);
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        std::thread::spawn(move || {
+            while !tx.is_closed() {
+                let Some(Ok(packet)) = follow.next_timeout(Duration::from_millis(100)) else {
+                    continue;
+                };
+                if tx.try_send(Ok((&packet).into())).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proto::store_client::StoreClient;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn test_service() {
+        let dir = tempfile::tempdir().unwrap();
+        let stacks = Stacks::open(dir.path().to_str().unwrap());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+        let server = tonic::transport::Server::builder()
+            .add_service(StoreService::new(stacks).into_server())
+            .serve_with_incoming(incoming);
+        tokio::spawn(server);
+
+        let mut client = StoreClient::connect(format!("http://{}", address))
+            .await
+            .unwrap();
+        let mut packets = client
+            .watch_packets(proto::WatchRequest { after: None })
+            .await
+            .unwrap()
+            .into_inner();
+        let added = client
+            .add(proto::AddRequest {
+                content: b"hello from grpc".to_vec(),
+                mime_type: "text/plain".to_string(),
+                stack_id: None,
+                source: Some("terminal".to_string()),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(added.kind(), proto::PacketKind::Add);
+        assert_eq!(added.source.as_deref(), Some("terminal"));
+        assert_eq!(packets.next().await.unwrap().unwrap(), added);
+
+        let hits = client
+            .search(proto::SearchRequest {
+                query: "grpc".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .hits;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, added.id);
+
+        let content = client
+            .get_content(proto::GetContentRequest {
+                id: added.id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(content.content, b"hello from grpc");
+        assert_eq!(content.mime_type, "text/plain");
+
+        let deleted = client
+            .delete(proto::DeleteRequest {
+                id: added.id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(deleted.source_id, Some(added.id.clone()));
+        assert_eq!(packets.next().await.unwrap().unwrap(), deleted);
+        let status = client
+            .update(proto::ChangeRequest {
+                id: added.id,
+                content: None,
+                mime_type: "text/plain".to_string(),
+                stack_id: None,
+                source: None,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+}
@@ -0,0 +1,89 @@
+use regex::Regex;
+
+use crate::redact::glob_match;
+use crate::source::Source;
+use crate::store::MimeType;
+
+/// Content `Store::add` refuses to store at all, see
+/// `Store::add_ignore_rule`.
+#[derive(Debug, Clone)]
+pub enum IgnoreRule {
+    /// Content from sources whose `app` matches the glob, and whose window
+    /// title matches `window_title` when that's given, e.g. password
+    /// managers or private browsing windows.
+    Source {
+        app: String,
+        window_title: Option<String>,
+    },
+    /// Text content containing a match of the pattern.
+    Pattern(Regex),
+}
+
+impl IgnoreRule {
+    pub fn app(glob: &str) -> Self {
+        IgnoreRule::Source {
+            app: glob.to_string(),
+            window_title: None,
+        }
+    }
+
+    pub fn pattern(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(IgnoreRule::Pattern(Regex::new(pattern)?))
+    }
+
+    pub(crate) fn matches(
+        &self,
+        content: &[u8],
+        mime_type: &MimeType,
+        source: Option<&Source>,
+    ) -> bool {
+        match self {
+            IgnoreRule::Source { app, window_title } => source.is_some_and(|source| {
+                glob_match(app, &source.app)
+                    && window_title.as_ref().is_none_or(|glob| {
+                        source
+                            .window_title
+                            .as_ref()
+                            .is_some_and(|title| glob_match(glob, title))
+                    })
+            }),
+            IgnoreRule::Pattern(pattern) => {
+                mime_type.is_text()
+                    && std::str::from_utf8(content).is_ok_and(|text| pattern.is_match(text))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let text = MimeType::TextPlain;
+        let source = |app: &str, window_title: Option<&str>| Source {
+            window_title: window_title.map(str::to_string),
+            ..Source::new(app)
+        };
+
+        let rule = IgnoreRule::app("1Password*");
+        assert!(rule.matches(b"x", &text, Some(&source("1Password 7", None))));
+        assert!(!rule.matches(b"x", &text, Some(&source("Terminal", None))));
+        assert!(!rule.matches(b"x", &text, None));
+
+        let rule = IgnoreRule::Source {
+            app: "Firefox".into(),
+            window_title: Some("*Private Browsing*".into()),
+        };
+        let private = source("Firefox", Some("News — Private Browsing"));
+        assert!(rule.matches(b"x", &text, Some(&private)));
+        assert!(!rule.matches(b"x", &text, Some(&source("Firefox", Some("News")))));
+        assert!(!rule.matches(b"x", &text, Some(&source("Firefox", None))));
+
+        let rule = IgnoreRule::pattern(r"^\d{6}$").unwrap();
+        assert!(rule.matches(b"123456", &text, None));
+        assert!(!rule.matches(b"123456", &MimeType::ImagePng, None));
+        assert!(!rule.matches(b"12345", &text, None));
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use scru128::Scru128Id;
+
+/// Supplies the keys of protected stacks, see `Store::protect_stack`. It's
+/// `Send` so a store can be handed to another thread, e.g. a server's.
+pub trait Keyring: Send {
+    fn key(&self, stack_id: &Scru128Id) -> Option<[u8; 32]>;
+}
+
+impl Keyring for HashMap<Scru128Id, [u8; 32]> {
+    fn key(&self, stack_id: &Scru128Id) -> Option<[u8; 32]> {
+        self.get(stack_id).copied()
+    }
+}
+
+const NONCE_LEN: usize = 24;
+
+/// A random key, e.g. for content sealed only for as long as a store is
+/// open.
+pub(crate) fn generate_key() -> [u8; 32] {
+    XChaCha20Poly1305::generate_key(&mut OsRng).into()
+}
+
+/// Encrypts `plaintext`, prefixing the result with a random nonce.
+pub(crate) fn seal(key: &[u8; 32], plaintext: &[u8]) -> Vec<u8> {
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(cipher.encrypt(&nonce, plaintext).unwrap());
+    sealed
+}
+
+/// Decrypts what `seal` produced, or `None` if the key is wrong or the
+/// content has been tampered with.
+pub(crate) fn open(key: &[u8; 32], sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    cipher.decrypt(XNonce::from_slice(nonce), ciphertext).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal() {
+        let key = [7; 32];
+        let sealed = seal(&key, b"hunter2");
+        assert!(!sealed.windows(7).any(|window| window == b"hunter2"));
+        assert_eq!(open(&key, &sealed), Some(b"hunter2".to_vec()));
+        assert_eq!(open(&[8; 32], &sealed), None);
+        assert_eq!(open(&key, &sealed[..10]), None);
+    }
+}
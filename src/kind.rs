@@ -0,0 +1,205 @@
+//! Classifies text content by what it looks like, so frontends can offer
+//! previews and actions that suit it: open a URL, swatch a color, and so on.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ContentKind {
+    Url,
+    Email,
+    /// A hex, `rgb()` or `hsl()` color.
+    Color,
+    Path,
+    Phone,
+    Json,
+    Code,
+}
+
+impl std::fmt::Display for ContentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ContentKind::Url => "URL",
+            ContentKind::Email => "email address",
+            ContentKind::Color => "color",
+            ContentKind::Path => "file path",
+            ContentKind::Phone => "phone number",
+            ContentKind::Json => "JSON",
+            ContentKind::Code => "code",
+        })
+    }
+}
+
+impl ContentKind {
+    pub(crate) const ALL: [ContentKind; 7] = [
+        ContentKind::Url,
+        ContentKind::Email,
+        ContentKind::Color,
+        ContentKind::Path,
+        ContentKind::Phone,
+        ContentKind::Json,
+        ContentKind::Code,
+    ];
+
+    /// The kind's name in queries, e.g. `kind:url`.
+    pub(crate) fn name(&self) -> String {
+        format!("{:?}", self).to_lowercase()
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<ContentKind> {
+        let name = name.to_lowercase();
+        ContentKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+}
+
+const CODE_KEYWORDS: &[&str] = &[
+    "fn", "pub", "let", "const", "var", "function", "def", "class", "import", "from", "return",
+    "if", "for", "while", "#include", "package", "use", "struct", "impl",
+];
+
+/// The kind of `text`, if it's all one thing. Prose is `None`.
+pub fn classify(text: &str) -> Option<ContentKind> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    if (text.starts_with('{') || text.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(text).is_ok()
+    {
+        return Some(ContentKind::Json);
+    }
+    if !text.contains('\n') {
+        if is_url(text) {
+            return Some(ContentKind::Url);
+        }
+        if is_email(text) {
+            return Some(ContentKind::Email);
+        }
+        if is_color(text) {
+            return Some(ContentKind::Color);
+        }
+        if is_phone(text) {
+            return Some(ContentKind::Phone);
+        }
+        if is_path(text) {
+            return Some(ContentKind::Path);
+        }
+    }
+    is_code(text).then_some(ContentKind::Code)
+}
+
+fn is_url(text: &str) -> bool {
+    let rest = ["https://", "http://", "ftp://", "www."]
+        .iter()
+        .find_map(|prefix| text.strip_prefix(prefix));
+    rest.is_some_and(|rest| !rest.is_empty() && !rest.contains(char::is_whitespace))
+}
+
+fn is_email(text: &str) -> bool {
+    let Some((local, domain)) = text.split_once('@') else {
+        return false;
+    };
+    let tld = domain.rsplit('.').next().unwrap();
+    !local.is_empty()
+        && !text.contains(char::is_whitespace)
+        && !domain.contains('@')
+        && domain.contains('.')
+        && tld.len() >= 2
+        && tld.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn is_color(text: &str) -> bool {
+    if let Some(hex) = text.strip_prefix('#') {
+        return [3, 4, 6, 8].contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    let lower = text.to_ascii_lowercase();
+    let args = ["rgb(", "rgba(", "hsl(", "hsla("]
+        .iter()
+        .find_map(|prefix| lower.strip_prefix(prefix))
+        .and_then(|rest| rest.strip_suffix(')'));
+    args.is_some_and(|args| {
+        args.chars().any(|c| c.is_ascii_digit())
+            && args
+                .chars()
+                .all(|c| c.is_ascii_digit() || " ,./%deg".contains(c))
+    })
+}
+
+fn is_phone(text: &str) -> bool {
+    let digits = text.chars().filter(|c| c.is_ascii_digit()).count();
+    (7..=15).contains(&digits)
+        && text
+            .strip_prefix('+')
+            .unwrap_or(text)
+            .chars()
+            .all(|c| c.is_ascii_digit() || " ().-".contains(c))
+}
+
+fn is_path(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let windows =
+        bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\';
+    let unix = ["/", "~/", "./", "../"]
+        .iter()
+        .any(|prefix| text.starts_with(prefix))
+        && text.len() > 1
+        && !text.starts_with("//");
+    windows || unix
+}
+
+/// Whether most lines open with a keyword or end with punctuation that
+/// prose rarely does.
+fn is_code(text: &str) -> bool {
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let code_lines = lines
+        .iter()
+        .filter(|line| {
+            let first = line
+                .split(|c: char| !(c.is_alphanumeric() || c == '#' || c == '_'))
+                .next()
+                .unwrap();
+            CODE_KEYWORDS.contains(&first)
+                || line.ends_with(';')
+                || line.ends_with('{')
+                || line.ends_with('}')
+                || line.ends_with(':') && line.starts_with(|c: char| c.is_ascii_lowercase())
+        })
+        .count();
+    code_lines >= 2 && code_lines * 2 >= lines.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        for (text, kind) in [
+            ("https://example.com/a?b=c", Some(ContentKind::Url)),
+            ("www.example.com", Some(ContentKind::Url)),
+            ("jo@example.com", Some(ContentKind::Email)),
+            ("#ff8800", Some(ContentKind::Color)),
+            ("rgba(255, 136, 0, 0.5)", Some(ContentKind::Color)),
+            ("+1 (555) 123-4567", Some(ContentKind::Phone)),
+            ("~/Documents/notes.txt", Some(ContentKind::Path)),
+            ("C:\\Users\\jo", Some(ContentKind::Path)),
+            ("{\"a\": [1, 2]}", Some(ContentKind::Json)),
+            (
+                "fn main() {\n    println!(\"hi\");\n}",
+                Some(ContentKind::Code),
+            ),
+            ("Meeting notes: ship the release on Thursday", None),
+            ("see https://example.com", None),
+            ("#hashtag", None),
+            ("2024", None),
+            ("{not json", None),
+        ] {
+            assert_eq!(classify(text), kind, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Guesses the programming language of code, by counting tokens that are
+//! characteristic of each language.
+
+/// Tokens that suggest a language. Each occurrence scores a point.
+const SIGNATURES: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            "fn ", "let mut ", "impl ", "pub fn", "::", "println!", "-> ", "&self", "Some(",
+        ],
+    ),
+    (
+        "python",
+        &[
+            "def ", "elif ", "self.", "import ", "print(", "None", "__init__", "):\n",
+        ],
+    ),
+    (
+        "typescript",
+        &[
+            "interface ",
+            ": string",
+            ": number",
+            ": boolean",
+            "export type ",
+            "=> ",
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            "function ",
+            "const ",
+            "=> ",
+            "console.log",
+            "===",
+            "require(",
+            "let ",
+        ],
+    ),
+    (
+        "go",
+        &["func ", "package ", ":= ", "fmt.", "err != nil", "go "],
+    ),
+    (
+        "c",
+        &["#include", "printf(", "int main", "malloc(", "->", "NULL"],
+    ),
+    (
+        "java",
+        &[
+            "public class",
+            "System.out",
+            "public static void",
+            "private ",
+            "new ",
+            "@Override",
+        ],
+    ),
+    (
+        "shell",
+        &["#!/bin/", "echo ", "fi\n", "$(", "then\n", "export "],
+    ),
+    (
+        "sql",
+        &[
+            "SELECT ",
+            "FROM ",
+            "WHERE ",
+            "INSERT INTO",
+            "CREATE TABLE",
+            "JOIN ",
+        ],
+    ),
+    (
+        "html",
+        &["<div", "</", "<html", "<p>", "class=\"", "<!DOCTYPE"],
+    ),
+];
+
+/// The language `code` most likely is, named in lowercase, e.g. `"rust"`.
+/// `None` if no language scores at least two.
+pub fn detect(code: &str) -> Option<&'static str> {
+    let mut best = None;
+    let mut best_score = 1;
+    for (language, tokens) in SIGNATURES {
+        let score: usize = tokens.iter().map(|token| code.matches(token).count()).sum();
+        if score > best_score {
+            best = Some(*language);
+            best_score = score;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        for (code, language) in [
+            (
+                "fn main() {\n    let mut x = Vec::new();\n    println!(\"{:?}\", x);\n}",
+                Some("rust"),
+            ),
+            (
+                "def greet(name):\n    if name is None:\n        print('hi')\n",
+                Some("python"),
+            ),
+            ("func main() {\n\tx := 1\n\tfmt.Println(x)\n}", Some("go")),
+            (
+                "SELECT id, name\nFROM users\nWHERE active = 1;",
+                Some("sql"),
+            ),
+            ("x = 1;\ny = 2;", None),
+        ] {
+            assert_eq!(detect(code), language, "{}", code);
+        }
+    }
+}
@@ -1,31 +1,104 @@
+#[cfg(feature = "archive")]
+pub mod archive;
+mod auto_stack;
+#[cfg(feature = "encryption")]
+pub mod bundle;
+mod cdc;
+mod chunk;
+pub mod context;
+#[cfg(feature = "image")]
+mod convert;
+mod derived;
+mod exif;
+pub mod export;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod ignore;
+mod keyring;
+mod kind;
+mod language;
+mod link_preview;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+#[cfg(feature = "node")]
+mod node;
+#[cfg(feature = "python")]
+mod python;
+mod raw;
+mod read_only;
+mod reader;
+mod redact;
+mod search;
+mod secrets;
+mod source;
+mod stacks;
 mod store;
+#[cfg(feature = "swift")]
+mod swift;
+#[cfg(feature = "tauri")]
+pub mod tauri;
+mod terse;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod uri_list;
 mod view;
+pub mod wire;
 
-pub use crate::store::Store;
-pub use crate::view::View;
+#[cfg(feature = "swift")]
+uniffi::setup_scaffolding!("s2");
+
+pub use crate::auto_stack::AutoStack;
+pub use crate::cdc::{CdcSink, CdcTarget};
+#[cfg(feature = "image")]
+pub use crate::convert::ConvertError;
+pub use crate::derived::DerivedKind;
+pub use crate::exif::ExifPolicy;
+pub use crate::ignore::IgnoreRule;
+pub use crate::keyring::Keyring;
+pub use crate::kind::ContentKind;
+pub use crate::link_preview::LinkPreview;
+pub use crate::raw::{PacketRef, RawPacket};
+pub use crate::read_only::ReadOnlyStore;
+pub use crate::reader::StoreReader;
+pub use crate::redact::RedactionRule;
+pub use crate::search::{CancelToken, SearchClause, SearchQuery, SearchResults, SearchSort};
+pub use crate::secrets::{is_confidential, ConfidentialPolicy, SecretKind, SecretPolicy};
+pub use crate::source::Source;
+pub use crate::stacks::Stacks;
+pub use crate::store::{
+    AccessKind, AccessPacket, AddError, AddPacket, AppendChunk, CasError, Content, Corruption,
+    Cursor, DeletePacket, Divergence, Draft, EphemeralItem, Extractor, Follow, ForkPacket,
+    GrepMatch, IndexOptions, IndexSize, InsertError, Inserted, InvalidOperation, MergePolicy,
+    MimeType, OpenError, OrphanPolicy, OversizePolicy, Packet, PacketFilter, PacketKind,
+    PolicyPacket, Purged, Reindex, ReloadPolicy, RepairOrphansError, Repaired, SearchOptions,
+    SizeLimit, SnapshotPacket, Store, StoreBuilder, StoreOptions, StoreWriter, TypeCounts,
+    UndecodablePacket, UpdatePacket, Version, ENGLISH_STOP_WORDS,
+};
+pub use crate::terse::{StructuredFormat, TerseOptions};
+pub use crate::view::{
+    Accesses, BudgetMeasure, BudgetOrder, ChildOrder, ConflictPolicy, Item, MergeError, SortSpec,
+    View, ViewEvent, ViewStats, Walk, FRECENCY_HALF_LIFE_MILLIS,
+};
 
 #[cfg(test)]
 mod tests {
     use crate::store::{MimeType, Store};
-    use crate::view::View;
+    use crate::view::{BudgetMeasure, BudgetOrder, ChildOrder, Item, View};
 
     fn assert_view_as_expected(store: &Store, view: &View, expected: Vec<(&str, Vec<&str>)>) {
         let actual: Vec<(String, Vec<String>)> = view
-            .root()
-            .iter()
+            .iter_root()
             .filter_map(|item| {
                 let children = view
-                    .children(&item)
-                    .iter()
-                    .filter_map(|child_id| {
-                        view.items
-                            .get(child_id)
-                            .and_then(|child_item| store.cas_read(&child_item.hash))
-                            .map(|content| String::from_utf8_lossy(&content).into_owned())
-                    })
+                    .iter_children(item)
+                    .filter_map(|child| store.cas_read(&child.hash).unwrap())
+                    .map(|content| String::from_utf8_lossy(&content).into_owned())
                     .collect::<Vec<_>>();
                 store
                     .cas_read(&item.hash)
+                    .unwrap()
                     .map(|s| (String::from_utf8_lossy(&s).into_owned(), children))
             })
             .collect();
@@ -51,20 +124,26 @@ mod tests {
         let mut store = Store::new(path);
         let mut view = View::new();
 
-        let stack_id = store.add(b"Stack 1", MimeType::TextPlain, None, None).id();
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
         let item_id = store
             .add(b"Item 1", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
             .id();
         // User updates the item
-        store.update(
-            item_id,
-            Some(b"Item 1 - updated"),
-            MimeType::TextPlain,
-            None,
-            None,
-        );
+        store
+            .update(
+                item_id,
+                Some(b"Item 1 - updated"),
+                MimeType::TextPlain,
+                None,
+                None,
+            )
+            .unwrap();
 
-        store.scan().for_each(|p| view.merge(p));
+        store.scan().for_each(|p| view.merge(p.unwrap()));
         assert_view_as_expected(&store, &view, vec![("Stack 1", vec!["Item 1 - updated"])]);
     }
 
@@ -76,21 +155,27 @@ mod tests {
         let mut store = Store::new(path);
         let mut view = View::new();
 
-        let stack_id = store.add(b"Stack 1", MimeType::TextPlain, None, None).id();
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
         let item_id = store
             .add(b"Item 1", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
             .id();
 
         // User forks the original item
-        store.fork(
-            item_id,
-            Some(b"Item 1 - forked"),
-            MimeType::TextPlain,
-            None,
-            None,
-        );
+        store
+            .fork(
+                item_id,
+                Some(b"Item 1 - forked"),
+                MimeType::TextPlain,
+                None,
+                None,
+            )
+            .unwrap();
 
-        store.scan().for_each(|p| view.merge(p));
+        store.scan().for_each(|p| view.merge(p.unwrap()));
         assert_view_as_expected(
             &store,
             &view,
@@ -106,18 +191,27 @@ mod tests {
         let mut store = Store::new(path);
         let mut view = View::new();
 
-        let stack_id = store.add(b"Stack 1", MimeType::TextPlain, None, None).id();
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
         let item_id = store
             .add(b"Item 1", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
             .id();
 
         // User creates a new Stack "Stack 2"
-        let stack_id_2 = store.add(b"Stack 2", MimeType::TextPlain, None, None).id();
+        let stack_id_2 = store
+            .add(b"Stack 2", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
 
         // User moves the original item to "Stack 2"
-        store.update(item_id, None, MimeType::TextPlain, Some(stack_id_2), None);
+        store
+            .update(item_id, None, MimeType::TextPlain, Some(stack_id_2), None)
+            .unwrap();
 
-        store.scan().for_each(|p| view.merge(p));
+        store.scan().for_each(|p| view.merge(p.unwrap()));
         assert_view_as_expected(
             &store,
             &view,
@@ -133,18 +227,23 @@ mod tests {
         let mut store = Store::new(path);
         let mut view = View::new();
 
-        let stack_id = store.add(b"Stack 1", MimeType::TextPlain, None, None).id();
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
         let item_id_1 = store
             .add(b"Item 1", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
             .id();
         let _item_id_2 = store
             .add(b"Item 2", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
             .id();
 
         // User deletes the first item
-        store.delete(item_id_1);
+        store.delete(item_id_1).unwrap();
 
-        store.scan().for_each(|p| view.merge(p));
+        store.scan().for_each(|p| view.merge(p.unwrap()));
         assert_view_as_expected(&store, &view, vec![("Stack 1", vec!["Item 2"])]);
     }
 
@@ -155,21 +254,27 @@ mod tests {
 
         let mut store = Store::new(path);
 
-        let stack_id = store.add(b"Stack 1", MimeType::TextPlain, None, None).id();
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
         let item_id_1 = store
             .add(b"Item 1", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
             .id();
         let item_id_2 = store
             .add(b"Item 2", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
             .id();
 
         // User forks the stack
         let new_stack_id = store
             .fork(stack_id, Some(b"Stack 2"), MimeType::TextPlain, None, None)
+            .unwrap()
             .id();
 
         let mut view = View::new();
-        store.scan().for_each(|p| view.merge(p));
+        store.scan().for_each(|p| view.merge(p.unwrap()));
         assert_view_as_expected(
             &store,
             &view,
@@ -180,23 +285,27 @@ mod tests {
         );
 
         // User forks the items to the new stack
-        store.fork(
-            item_id_1,
-            None,
-            MimeType::TextPlain,
-            Some(new_stack_id),
-            None,
-        );
-        store.fork(
-            item_id_2,
-            None,
-            MimeType::TextPlain,
-            Some(new_stack_id),
-            None,
-        );
+        store
+            .fork(
+                item_id_1,
+                None,
+                MimeType::TextPlain,
+                Some(new_stack_id),
+                None,
+            )
+            .unwrap();
+        store
+            .fork(
+                item_id_2,
+                None,
+                MimeType::TextPlain,
+                Some(new_stack_id),
+                None,
+            )
+            .unwrap();
 
         let mut view = View::new();
-        store.scan().for_each(|p| view.merge(p));
+        store.scan().for_each(|p| view.merge(p.unwrap()));
         assert_view_as_expected(
             &store,
             &view,
@@ -206,4 +315,842 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn test_select_within_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let mut store = Store::new(path);
+
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        let item_id_1 = store
+            .add(b"1234", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+        let item_id_2 = store
+            .add(b"12345678", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+        let item_id_3 = store
+            .add(b"123", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+
+        assert_eq!(
+            view.select_within_budget(&store, stack_id, 12, BudgetOrder::MostRecent),
+            (vec![item_id_3, item_id_2], 11)
+        );
+        // item 2 doesn't fit after item 1, but item 3 still does
+        assert_eq!(
+            view.select_within_budget(&store, stack_id, 10, BudgetOrder::LeastRecent),
+            (vec![item_id_1, item_id_3], 7)
+        );
+        assert_eq!(
+            view.select_within_budget(&store, stack_id, 2, BudgetOrder::MostRecent),
+            (vec![], 0)
+        );
+    }
+
+    #[test]
+    fn test_select_within_budget_measured() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        let short = store
+            .add(b"one two", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap();
+        let long = store
+            .add(b"a\nb\nc d e", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap();
+        let meta = store.content_meta(long.hash().unwrap()).unwrap();
+        assert_eq!(
+            (meta.counts.lines, meta.counts.words, meta.counts.chars),
+            (3, 5, 9)
+        );
+
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        assert_eq!(
+            view.select_within_budget_measured(
+                &store,
+                stack_id,
+                2,
+                BudgetMeasure::Lines,
+                BudgetOrder::MostRecent
+            ),
+            (vec![short.id()], 1)
+        );
+        assert_eq!(
+            view.select_within_budget_measured(
+                &store,
+                stack_id,
+                7,
+                BudgetMeasure::Words,
+                BudgetOrder::LeastRecent
+            ),
+            (vec![short.id(), long.id()], 7)
+        );
+    }
+
+    #[test]
+    fn test_root_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let mut store = Store::new(path);
+        let mut view = View::new();
+
+        let packet = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap();
+        let stack_id_1 = packet.id();
+        view.merge(packet);
+        let packet = store
+            .add(b"Stack 2", MimeType::TextPlain, None, None)
+            .unwrap();
+        let stack_id_2 = packet.id();
+        view.merge(packet);
+        assert_eq!(view.root_ids(), &[stack_id_1, stack_id_2]);
+
+        // touching a stack through one of its children moves it to the end
+        let item = store
+            .add(b"Item", MimeType::TextPlain, Some(stack_id_1), None)
+            .unwrap();
+        view.merge(item.clone());
+        assert_eq!(view.root_ids(), &[stack_id_2, stack_id_1]);
+
+        view.merge(
+            store
+                .update(
+                    stack_id_2,
+                    Some(b"Stack 2 - updated"),
+                    MimeType::TextPlain,
+                    None,
+                    None,
+                )
+                .unwrap(),
+        );
+        assert_eq!(view.root_ids(), &[stack_id_1, stack_id_2]);
+
+        view.merge(store.delete(stack_id_2).unwrap());
+        assert_eq!(view.root_ids(), &[stack_id_1]);
+        assert_eq!(view.get(&stack_id_1).unwrap().children, vec![item.id()]);
+        assert!(view.get(&stack_id_2).is_none());
+        assert_eq!(view.root(), view.iter_root().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_child_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let mut store = Store::new(path);
+
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        let stack_id_2 = store
+            .add(b"Stack 2", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        let item_id_1 = store
+            .add(b"Item 1", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+        let item_id_2 = store
+            .add(b"Item 2", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+        let item_id_3 = store
+            .add(b"Item 3", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+
+        // Item 1 is edited, and moved away and back again
+        store
+            .update(
+                item_id_1,
+                Some(b"Item 1 - updated"),
+                MimeType::TextPlain,
+                None,
+                None,
+            )
+            .unwrap();
+        store
+            .update(item_id_1, None, MimeType::TextPlain, Some(stack_id_2), None)
+            .unwrap();
+        store
+            .update(item_id_1, None, MimeType::TextPlain, Some(stack_id), None)
+            .unwrap();
+        // and Item 2 is edited in place
+        store
+            .update(
+                item_id_2,
+                Some(b"Item 2 - updated"),
+                MimeType::TextPlain,
+                None,
+                None,
+            )
+            .unwrap();
+
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        let stack = view.get(&stack_id).unwrap();
+        assert_eq!(stack.children, vec![item_id_1, item_id_2, item_id_3]);
+        assert_eq!(view.children(stack), vec![item_id_1, item_id_2, item_id_3]);
+
+        let mut view = View::with_child_order(ChildOrder::LastTouched);
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        let stack = view.get(&stack_id).unwrap();
+        assert_eq!(view.children(stack), vec![item_id_3, item_id_1, item_id_2]);
+    }
+
+    #[test]
+    fn test_frecency_order() {
+        use crate::view::SortSpec;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let ids: Vec<_> = ["hot", "cold", "warm"]
+            .iter()
+            .map(|content| {
+                store
+                    .add(content.as_bytes(), MimeType::TextPlain, None, None)
+                    .unwrap()
+                    .id()
+            })
+            .collect();
+        let touch = |store: &mut Store, id, times| {
+            for _ in 0..times {
+                store
+                    .update(id, None, MimeType::TextPlain, None, None)
+                    .unwrap();
+            }
+        };
+        touch(&mut store, ids[0], 3);
+        touch(&mut store, ids[2], 1);
+
+        let mut view = View::with_root_order(SortSpec::Frecency);
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        assert_eq!(view.root_ids(), &[ids[1], ids[2], ids[0]]);
+        let now = ids[0].timestamp();
+        let hot = view.get(&ids[0]).unwrap().frecency(now);
+        assert!((hot - 4.0).abs() < 0.01, "{}", hot);
+
+        // kept current as packets merge
+        let merged = store.scan().last().unwrap().unwrap().id();
+        touch(&mut store, ids[1], 4);
+        store
+            .scan()
+            .map(Result::unwrap)
+            .filter(|p| p.id() > merged)
+            .for_each(|p| view.merge(p));
+        assert_eq!(view.root_ids(), &[ids[2], ids[0], ids[1]]);
+
+        let stack = store
+            .add(b"stack", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        for &id in &ids {
+            store
+                .update(id, None, MimeType::TextPlain, Some(stack), None)
+                .unwrap();
+        }
+        let mut view = View::with_child_order(ChildOrder::Frecency);
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        let stack = view.get(&stack).unwrap();
+        assert_eq!(view.children(stack), vec![ids[2], ids[0], ids[1]]);
+    }
+
+    #[test]
+    fn test_record_access() {
+        use crate::store::{AccessKind, PacketFilter, PacketKind};
+        use crate::view::SortSpec;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let command = store
+            .add(b"cargo test", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        let note = store
+            .add(b"a note", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        store.record_access(command, AccessKind::Pasted);
+        store.record_access(command, AccessKind::Executed);
+        let last = store.record_access(command, AccessKind::Pasted);
+
+        let mut view = View::with_root_order(SortSpec::Frecency);
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        let item = view.get(&command).unwrap();
+        assert_eq!(item.accesses.count(AccessKind::Pasted), 2);
+        assert_eq!(item.accesses.count(AccessKind::Executed), 1);
+        assert_eq!(item.accesses.total(), 3);
+        assert_eq!(item.accesses.last_accessed, Some(last.id()));
+        // used, but not touched
+        assert_eq!(item.touched, vec![command]);
+        assert_eq!(view.root_ids(), &[note, command]);
+
+        let filter = PacketFilter {
+            kinds: Some(vec![PacketKind::Access]),
+            ..Default::default()
+        };
+        assert_eq!(store.scan_filtered(filter).count(), 3);
+    }
+
+    #[test]
+    fn test_repair_orphans() {
+        use crate::store::{InvalidOperation, OrphanPolicy, RepairOrphansError};
+
+        // an item left behind by its deleted stack, with a child of its own
+        let orphaned = |path: &std::path::Path| {
+            let mut store = Store::new(path.to_str().unwrap());
+            let mut add = |content: &str, stack_id| {
+                store
+                    .add(content.as_bytes(), MimeType::TextPlain, stack_id, None)
+                    .unwrap()
+                    .id()
+            };
+            let stack = add("Stack", None);
+            let orphan = add("Orphan", Some(stack));
+            let child = add("Child", Some(orphan));
+            let recovered = add("Recovered", None);
+            store.delete(stack).unwrap();
+            let mut view = View::new();
+            store.scan().for_each(|p| view.merge(p.unwrap()));
+            (store, view, [orphan, child, recovered])
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let (mut store, mut view, [orphan, _, recovered]) = orphaned(dir.path());
+        let orphans: Vec<_> = view.orphans().iter().map(|item| item.id).collect();
+        assert_eq!(orphans, vec![orphan]);
+        assert_eq!(view.root_ids(), &[recovered]);
+        let missing = scru128::new();
+        assert_eq!(
+            store.repair_orphans(&view, OrphanPolicy::Stack(missing)),
+            Err(RepairOrphansError {
+                written: Vec::new(),
+                error: InvalidOperation::UnknownStack(missing)
+            })
+        );
+        let packets = store
+            .repair_orphans(&view, OrphanPolicy::Stack(recovered))
+            .unwrap();
+        packets.into_iter().for_each(|p| view.merge(p));
+        assert!(view.orphans().is_empty());
+        assert_eq!(view.get(&recovered).unwrap().children, vec![orphan]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let (mut store, mut view, [orphan, child, recovered]) = orphaned(dir.path());
+        let packets = store.repair_orphans(&view, OrphanPolicy::Root).unwrap();
+        packets.into_iter().for_each(|p| view.merge(p));
+        assert!(view.orphans().is_empty());
+        assert!(view.get(&orphan).is_none());
+        assert_eq!(view.root_ids()[0], recovered);
+        let readded = view.get(&view.root_ids()[1]).unwrap();
+        assert_eq!(view.children(readded), vec![child]);
+        assert_eq!(store.cas_read(&readded.hash).unwrap().unwrap(), b"Orphan");
+
+        // stopping partway, what was written is returned
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let mut add = |content: &[u8], stack_id| {
+            store
+                .add(content, MimeType::TextPlain, stack_id, None)
+                .unwrap()
+                .id()
+        };
+        let vault = add(b"Vault", None);
+        let sealed = add(b"sealed", Some(vault));
+        let stack = add(b"Stack", None);
+        let plain = add(b"plain", Some(stack));
+        let recovered = add(b"Recovered", None);
+        let keyring = std::collections::HashMap::from([(vault, [1; 32]), (recovered, [2; 32])]);
+        store.set_keyring(Box::new(keyring));
+        store.protect_stack(vault).unwrap();
+        store.protect_stack(recovered).unwrap();
+        store.delete(vault).unwrap();
+        store.delete(stack).unwrap();
+        store.set_keyring(Box::new(std::collections::HashMap::new()));
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        // already sealed, it moves without the key, but the plain one can't
+        let err = store
+            .repair_orphans(&view, OrphanPolicy::Stack(recovered))
+            .unwrap_err();
+        assert_eq!(err.error, InvalidOperation::NoKey(recovered));
+        assert_eq!(err.written.len(), 1);
+        err.written.into_iter().for_each(|p| view.merge(p));
+        let orphans: Vec<_> = view.orphans().iter().map(|item| item.id).collect();
+        assert_eq!(orphans, vec![plain]);
+        assert_eq!(view.get(&recovered).unwrap().children, vec![sealed]);
+    }
+
+    #[test]
+    fn test_drafts() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let mut view = View::new();
+
+        let stack = store.draft_add(b"Compose", MimeType::TextPlain, None, None);
+        let note = store.draft_add(b"Note", MimeType::TextPlain, Some(stack.id()), None);
+        let (stack, note) = (stack.id(), note.id());
+        store
+            .drafts()
+            .for_each(|draft| view.merge(draft.packet.clone()));
+        assert_eq!(view.root_ids(), &[stack]);
+        assert_eq!(view.get(&stack).unwrap().children, vec![note]);
+        assert_eq!(store.scan().count(), 0);
+
+        let packets = store.commit_draft(stack).unwrap().unwrap();
+        packets.into_iter().for_each(|p| view.merge(p));
+        let committed: Vec<_> = store.scan().map(Result::unwrap).collect();
+        assert_eq!(committed.len(), 1);
+        let stack_id = committed[0].id();
+        assert_eq!(view.root_ids(), &[stack_id]);
+        assert_eq!(view.get(&stack_id).unwrap().children, vec![note]);
+        assert_eq!(
+            store.cas_read(&view.get(&stack_id).unwrap().hash).unwrap(),
+            Some(b"Compose".to_vec())
+        );
+        assert_eq!(store.draft(&note).unwrap().content, b"Note");
+
+        view.merge(store.discard_draft(note).unwrap());
+        assert!(view.get(&stack_id).unwrap().children.is_empty());
+        assert_eq!(store.drafts().count(), 0);
+        assert_eq!(store.scan().count(), 1);
+        assert!(store.commit_draft(note).is_none());
+    }
+
+    #[test]
+    fn test_view_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let stack = store
+            .add(b"Stack", MimeType::TextPlain, None, None)
+            .unwrap();
+        let item = store
+            .add(b"Item", MimeType::TextPlain, Some(stack.id()), None)
+            .unwrap();
+        for n in 0..10 {
+            let content = format!("Item {}", n);
+            store
+                .update(
+                    item.id(),
+                    Some(content.as_bytes()),
+                    MimeType::TextPlain,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        store
+            .fork(item.id(), None, MimeType::TextPlain, None, None)
+            .unwrap();
+
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        let stats = view.stats();
+        assert_eq!(stats.items, 3);
+        // the add, its updates and its fork
+        assert_eq!(stats.max_touched, 12);
+        assert_eq!(stats.children, 2);
+        assert!(stats.touched > stats.max_touched);
+        assert!(stats.bytes > 3 * std::mem::size_of::<Item>());
+    }
+
+    #[test]
+    fn test_try_merge() {
+        use crate::store::AccessKind;
+        use crate::view::{MergeError, ViewEvent};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let stack = store
+            .add(b"Stack", MimeType::TextPlain, None, None)
+            .unwrap();
+        let item = store.add(b"Item", MimeType::TextPlain, None, None).unwrap();
+        let id = item.id();
+        let edit = store
+            .update(id, Some(b"Edited"), MimeType::TextPlain, None, None)
+            .unwrap();
+        let mv = store
+            .update(id, None, MimeType::TextPlain, Some(stack.id()), None)
+            .unwrap();
+        let touch = store
+            .update(id, None, MimeType::TextPlain, None, None)
+            .unwrap();
+        let access = store.record_access(id, AccessKind::Pasted);
+        let delete = store.delete(id).unwrap();
+        let late = store
+            .update(id, None, MimeType::TextPlain, None, None)
+            .unwrap();
+
+        let mut view = View::new();
+        assert_eq!(
+            view.try_merge(edit.clone()),
+            Err(MergeError::UnknownSource {
+                id: edit.id(),
+                source_id: id
+            })
+        );
+        assert_eq!(
+            view.try_merge(stack.clone()),
+            Ok(vec![ViewEvent::Added(stack.id())])
+        );
+        assert_eq!(view.try_merge(item.clone()), Ok(vec![ViewEvent::Added(id)]));
+        assert_eq!(view.try_merge(item), Err(MergeError::DuplicateId(id)));
+        assert_eq!(view.try_merge(edit), Ok(vec![ViewEvent::Updated(id)]));
+        assert_eq!(
+            view.try_merge(mv),
+            Ok(vec![ViewEvent::Moved {
+                id,
+                from: None,
+                to: Some(stack.id())
+            }])
+        );
+        assert_eq!(view.try_merge(touch), Ok(vec![ViewEvent::Touched(id)]));
+        assert_eq!(view.try_merge(access), Ok(vec![ViewEvent::Accessed(id)]));
+        assert_eq!(view.try_merge(delete), Ok(vec![ViewEvent::Deleted(id)]));
+        assert_eq!(
+            view.try_merge(late.clone()),
+            Err(MergeError::SourceDeleted {
+                id: late.id(),
+                source_id: id
+            })
+        );
+    }
+
+    #[test]
+    fn test_sub_view() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let mut store = Store::new(path);
+
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        let nested_id = store
+            .add(b"Nested", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+        store
+            .add(b"Item 1", MimeType::TextPlain, Some(nested_id), None)
+            .unwrap();
+        store
+            .add(b"Item 2", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap();
+        let other_id = store
+            .add(b"Stack 2", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        store
+            .add(b"Other", MimeType::TextPlain, Some(other_id), None)
+            .unwrap();
+
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+
+        let sub_view = view.sub_view(nested_id);
+        assert_eq!(sub_view.items.len(), 2);
+        assert_view_as_expected(&store, &sub_view, vec![("Nested", vec!["Item 1"])]);
+
+        let sub_view = view.sub_view(stack_id);
+        assert_eq!(sub_view.items.len(), 4);
+        assert_view_as_expected(
+            &store,
+            &sub_view,
+            vec![("Stack 1", vec!["Nested", "Item 2"])],
+        );
+
+        assert!(view.sub_view(scru128::new()).items.is_empty());
+    }
+
+    #[test]
+    fn test_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let mut store = Store::new(path);
+
+        let stack_id = store
+            .add(b"Stack 1", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        let nested_id = store
+            .add(b"Nested", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap()
+            .id();
+        store
+            .add(b"Item 1", MimeType::TextPlain, Some(nested_id), None)
+            .unwrap();
+        store
+            .add(b"Item 2", MimeType::TextPlain, Some(stack_id), None)
+            .unwrap();
+        store
+            .add(b"Stack 2", MimeType::TextPlain, None, None)
+            .unwrap();
+
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+
+        let walk = |items: Vec<(usize, &Item)>| {
+            items
+                .into_iter()
+                .map(|(depth, item)| {
+                    let content = store.cas_read(&item.hash).unwrap().unwrap();
+                    (depth, String::from_utf8(content).unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = |items: &[(usize, &str)]| {
+            items
+                .iter()
+                .map(|&(depth, s)| (depth, s.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            walk(view.iter_depth_first().collect()),
+            expected(&[
+                (0, "Stack 1"),
+                (1, "Nested"),
+                (2, "Item 1"),
+                (1, "Item 2"),
+                (0, "Stack 2"),
+            ])
+        );
+        assert_eq!(
+            walk(view.iter_breadth_first().collect()),
+            expected(&[
+                (0, "Stack 1"),
+                (0, "Stack 2"),
+                (1, "Nested"),
+                (1, "Item 2"),
+                (2, "Item 1"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_file_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = tempfile::tempdir().unwrap();
+        let kept = files.path().join("kept file.txt");
+        let moved = files.path().join("moved.txt");
+        std::fs::write(&kept, b"kept").unwrap();
+
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let mut view = View::new();
+        let uri_list = format!(
+            "file://{}\r\nfile://{}\r\n",
+            kept.to_str().unwrap().replace(' ', "%20"),
+            moved.to_str().unwrap()
+        );
+        let packet = store
+            .add(
+                uri_list.as_bytes(),
+                MimeType::from("text/uri-list"),
+                None,
+                None,
+            )
+            .unwrap();
+        view.merge(packet.clone());
+        let item = view.get(&packet.id()).unwrap();
+
+        assert!(store.content_meta(&item.hash).unwrap().is_uri_list());
+        let references = item.file_references(&store);
+        assert_eq!(references[0].path.as_ref(), Some(&kept));
+        let exists: Vec<bool> = references.iter().map(|r| r.exists()).collect();
+        assert_eq!(exists, vec![true, false]);
+
+        // plain text isn't read as a uri-list
+        let packet = store
+            .add(uri_list.trim().as_bytes(), MimeType::TextPlain, None, None)
+            .unwrap();
+        view.merge(packet.clone());
+        assert!(view
+            .get(&packet.id())
+            .unwrap()
+            .file_references(&store)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_search_items() {
+        use crate::search::{SearchClause, SearchQuery, SearchSort};
+        use crate::source::Source;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let stack_id = store
+            .add(b"Deploys", MimeType::TextPlain, None, None)
+            .unwrap()
+            .id();
+        let mut ids = Vec::new();
+        for (content, stack, app) in [
+            ("deploy the api server", Some(stack_id), "terminal"),
+            ("server deploy notes", Some(stack_id), "editor"),
+            ("deploy the web server", None, "terminal"),
+        ] {
+            let packet = store
+                .add(
+                    content.as_bytes(),
+                    MimeType::TextPlain,
+                    stack,
+                    Some(Source::new(app)),
+                )
+                .unwrap();
+            ids.push(packet.id());
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        let search = |query: SearchQuery| -> Vec<_> {
+            let mut found: Vec<_> = store
+                .search_items(&query, &view)
+                .hits
+                .into_iter()
+                .map(|item| item.id)
+                .collect();
+            if query.sort == SearchSort::Relevance {
+                found.sort();
+            }
+            found
+        };
+
+        assert_eq!(search(SearchQuery::new().terms("deploy server")), ids);
+        assert_eq!(
+            search(SearchQuery::new().phrase("deploy the")),
+            vec![ids[0], ids[2]]
+        );
+        assert_eq!(
+            search(
+                SearchQuery::new()
+                    .terms("deploy")
+                    .not(SearchClause::Terms("web".into()))
+                    .source("terminal")
+            ),
+            vec![ids[0]]
+        );
+        assert_eq!(
+            search(SearchQuery::new().terms("deploy").stack(stack_id)),
+            vec![ids[0], ids[1]]
+        );
+        assert_eq!(
+            search(
+                SearchQuery::new()
+                    .terms("server")
+                    .sort(SearchSort::Newest)
+                    .limit(2)
+            ),
+            vec![ids[2], ids[1]]
+        );
+        let since = ids[1].timestamp();
+        assert_eq!(
+            search(SearchQuery::new().source("terminal").since(since)),
+            vec![ids[2]]
+        );
+    }
+
+    #[test]
+    fn test_search_budget() {
+        use crate::search::{CancelToken, SearchQuery};
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        for n in 0..3 {
+            let content = format!("budgeted search {}", n);
+            store
+                .add(content.as_bytes(), MimeType::TextPlain, None, None)
+                .unwrap();
+        }
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+
+        let results = store.search_items(
+            &SearchQuery::new()
+                .terms("budgeted")
+                .timeout(Duration::from_secs(60)),
+            &view,
+        );
+        assert_eq!(results.hits.len(), 3);
+        assert!(!results.truncated);
+
+        let token = CancelToken::new();
+        let query = SearchQuery::new().terms("budgeted").cancel(token.clone());
+        token.cancel();
+        let results = store.search_items(&query, &view);
+        assert!(results.hits.is_empty());
+        assert!(results.truncated);
+
+        let query = SearchQuery::new().terms("budgeted").timeout(Duration::ZERO);
+        assert!(store.search_items(&query, &view).truncated);
+    }
+
+    #[test]
+    fn test_search_history() {
+        use crate::search::SearchQuery;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let note = store
+            .add(b"api key format sk-live", MimeType::TextPlain, None, None)
+            .unwrap();
+        store
+            .update(
+                note.id(),
+                Some(b"rotated, see the vault"),
+                MimeType::TextPlain,
+                None,
+                None,
+            )
+            .unwrap();
+        let mut view = View::new();
+        store.scan().for_each(|p| view.merge(p.unwrap()));
+        let search = |query: SearchQuery| -> Vec<_> {
+            let results = store.search_items(&query, &view);
+            results.hits.iter().map(|item| item.id).collect()
+        };
+
+        assert!(search(SearchQuery::new().terms("format")).is_empty());
+        assert_eq!(
+            search(SearchQuery::new().terms("format").include_history()),
+            vec![note.id()]
+        );
+        assert_eq!(
+            search(SearchQuery::new().terms("vault").include_history()),
+            vec![note.id()]
+        );
+
+        // content added again is current again
+        store
+            .add(b"api key format sk-live", MimeType::TextPlain, None, None)
+            .unwrap();
+        assert_eq!(
+            store
+                .index
+                .run(&SearchQuery::new().terms("format"))
+                .hits
+                .len(),
+            1
+        );
+    }
 }
@@ -0,0 +1,143 @@
+//! Titles, descriptions and favicons of the pages URL items point to. The
+//! store doesn't fetch anything itself: `Store::enrich_link_previews` is
+//! given a fetch function, and can be run on whatever thread or runtime the
+//! caller does its networking on.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use ssri::Integrity;
+
+pub const MIME_TYPE: &str = "application/vnd.s2.link-preview+json";
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// The favicon's content, in the CAS.
+    pub favicon: Option<Integrity>,
+}
+
+/// The URL a URL item's text points to, adding a scheme to `www.` URLs.
+pub(crate) fn url(text: &str) -> String {
+    let text = text.trim();
+    match text.starts_with("www.") {
+        true => format!("https://{}", text),
+        false => text.to_string(),
+    }
+}
+
+/// The title, description and favicon URL of the page at `url`. Open Graph
+/// tags win over `<title>` and `<meta name="description">`; without an icon
+/// link the favicon is assumed to be at `/favicon.ico`.
+pub(crate) fn parse(url: &str, html: &str) -> (Option<String>, Option<String>, String) {
+    let meta = |attribute: &str, name: &str| {
+        let tag = Regex::new(&format!(
+            r#"(?is)<meta[^>]*{}\s*=\s*["']{}["'][^>]*>"#,
+            attribute,
+            regex::escape(name)
+        ))
+        .unwrap();
+        let content = Regex::new(r#"(?is)content\s*=\s*["']([^"']*)["']"#).unwrap();
+        let tag = tag.find(html)?.as_str();
+        Some(decode(&content.captures(tag)?[1]))
+    };
+    let title = meta("property", "og:title").or_else(|| {
+        let title = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+        Some(decode(&title.captures(html)?[1]))
+    });
+    let description = meta("property", "og:description").or_else(|| meta("name", "description"));
+
+    let icon = Regex::new(r#"(?is)<link[^>]*rel\s*=\s*["'](?:shortcut )?icon["'][^>]*>"#).unwrap();
+    let href = Regex::new(r#"(?is)href\s*=\s*["']([^"']*)["']"#).unwrap();
+    let favicon = icon
+        .find(html)
+        .and_then(|tag| href.captures(tag.as_str()))
+        .map(|captures| captures[1].to_string())
+        .unwrap_or_else(|| "/favicon.ico".to_string());
+
+    let non_empty = |text: Option<String>| text.filter(|text| !text.is_empty());
+    (
+        non_empty(title),
+        non_empty(description),
+        resolve(url, &favicon),
+    )
+}
+
+/// `href` relative to the page at `base`.
+fn resolve(base: &str, href: &str) -> String {
+    if href.contains("://") {
+        return href.to_string();
+    }
+    let Some(scheme_end) = base.find("://").map(|i| i + 3) else {
+        return href.to_string();
+    };
+    let origin_end = base[scheme_end..]
+        .find('/')
+        .map_or(base.len(), |i| scheme_end + i);
+    match href {
+        href if href.starts_with("//") => format!("{}{}", &base[..scheme_end - 1], &href[1..]),
+        href if href.starts_with('/') => format!("{}{}", &base[..origin_end], href),
+        href => {
+            let dir_end = base[origin_end..]
+                .rfind('/')
+                .map_or(base.len(), |i| origin_end + i);
+            format!("{}/{}", &base[..dir_end], href)
+        }
+    }
+}
+
+/// Collapses whitespace and decodes the common entities.
+fn decode(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let html = r#"<html><head>
+            <title>
+              Fish &amp; Chips
+            </title>
+            <meta name="description" content="Where to eat">
+            <link rel="shortcut icon" href="/static/icon.png">
+        </head></html>"#;
+        assert_eq!(
+            parse("https://example.com/food/index.html", html),
+            (
+                Some("Fish & Chips".into()),
+                Some("Where to eat".into()),
+                "https://example.com/static/icon.png".into()
+            )
+        );
+
+        let html = r#"<meta property="og:title" content="Open Graph"><title>Plain</title>"#;
+        assert_eq!(
+            parse("https://example.com", html),
+            (
+                Some("Open Graph".into()),
+                None,
+                "https://example.com/favicon.ico".into()
+            )
+        );
+
+        assert_eq!(
+            resolve("https://example.com/a/b.html", "icon.png"),
+            "https://example.com/a/icon.png"
+        );
+        assert_eq!(
+            resolve("https://example.com/", "//cdn.example.com/i.ico"),
+            "https://cdn.example.com/i.ico"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Counters and histograms, recorded through the `metrics` crate, for
+//! monitoring a store running as a long-lived daemon. Nothing is recorded
+//! until a recorder is installed, e.g. Prometheus's:
+//!
+//! ```ignore
+//! let handle = s2::metrics::install_prometheus()?;
+//! // serve on the daemon's /metrics endpoint
+//! let body = handle.render();
+//! ```
+
+use std::time::Instant;
+
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
+
+use crate::store::PacketKind;
+
+/// Packets written, by `kind`; the rate of those of kind `add` is adds per
+/// second.
+pub const PACKETS_WRITTEN: &str = "s2_packets_written_total";
+pub const SEARCH_SECONDS: &str = "s2_search_seconds";
+pub const INDEX_COMMIT_SECONDS: &str = "s2_index_commit_seconds";
+/// Bytes written to the CAS, chunks included.
+pub const CAS_BYTES_WRITTEN: &str = "s2_cas_bytes_written_total";
+/// Blobs removed from the CAS, e.g. by `Store::purge_packet` or
+/// `Store::purge_expired`.
+pub const CAS_BLOBS_REMOVED: &str = "s2_cas_blobs_removed_total";
+/// Bytes `Index::optimize` freed by merging segments.
+pub const INDEX_BYTES_RECLAIMED: &str = "s2_index_bytes_reclaimed_total";
+
+/// Describes the metrics to the installed recorder.
+pub fn describe() {
+    use ::metrics::{describe_counter, describe_histogram, Unit};
+    describe_counter!(PACKETS_WRITTEN, "Packets written to the log");
+    describe_histogram!(
+        SEARCH_SECONDS,
+        Unit::Seconds,
+        "Time spent searching the index"
+    );
+    describe_histogram!(
+        INDEX_COMMIT_SECONDS,
+        Unit::Seconds,
+        "Time spent committing the index"
+    );
+    describe_counter!(CAS_BYTES_WRITTEN, Unit::Bytes, "Bytes written to the CAS");
+    describe_counter!(CAS_BLOBS_REMOVED, "Blobs removed from the CAS");
+    describe_counter!(
+        INDEX_BYTES_RECLAIMED,
+        Unit::Bytes,
+        "Bytes freed by optimizing the index"
+    );
+}
+
+/// Installs a Prometheus recorder as the global recorder, returning the
+/// handle that renders the metrics for scraping.
+pub fn install_prometheus() -> Result<PrometheusHandle, BuildError> {
+    let handle = PrometheusBuilder::new().install_recorder()?;
+    describe();
+    Ok(handle)
+}
+
+pub(crate) fn packet_written(kind: PacketKind) {
+    let kind = match kind {
+        PacketKind::Add => "add",
+        PacketKind::Update => "update",
+        PacketKind::Fork => "fork",
+        PacketKind::Delete => "delete",
+        PacketKind::Snapshot => "snapshot",
+        PacketKind::Access => "access",
+        PacketKind::Policy => "policy",
+    };
+    ::metrics::counter!(PACKETS_WRITTEN, "kind" => kind).increment(1);
+}
+
+/// Records the time since `started` in the histogram `name`.
+pub(crate) fn elapsed(name: &'static str, started: Instant) {
+    ::metrics::histogram!(name).record(started.elapsed().as_secs_f64());
+}
+
+pub(crate) fn cas_written(bytes: usize) {
+    ::metrics::counter!(CAS_BYTES_WRITTEN).increment(bytes as u64);
+}
+
+pub(crate) fn cas_removed() {
+    ::metrics::counter!(CAS_BLOBS_REMOVED).increment(1);
+}
+
+pub(crate) fn index_reclaimed(bytes: u64) {
+    ::metrics::counter!(INDEX_BYTES_RECLAIMED).increment(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::store::{MimeType, Store};
+
+    #[test]
+    fn test_metrics() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        ::metrics::with_local_recorder(&recorder, || {
+            let dir = tempfile::tempdir().unwrap();
+            let mut store = Store::new(dir.path().to_str().unwrap());
+            let packet = store
+                .add(b"measured", MimeType::TextPlain, None, None)
+                .unwrap();
+            store.index.query("measured");
+            store.purge_packet(&packet.id()).unwrap();
+        });
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"s2_packets_written_total{kind="add"} 1"#));
+        assert!(rendered.contains("s2_cas_bytes_written_total 8"));
+        assert!(rendered.contains("s2_cas_blobs_removed_total 1"));
+        assert!(rendered.contains("s2_search_seconds_count 1"));
+        assert!(rendered.contains("s2_index_commit_seconds_count"));
+    }
+}
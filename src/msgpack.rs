@@ -0,0 +1,109 @@
+//! MessagePack encodings, the compact form for IPC between the engine
+//! running as a daemon and GUI shells. Each has the shape of the JSON form,
+//! field names and all, so a shell can switch between them: packets as in
+//! `wire`, items and search results as they serialize.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::search::SearchResults;
+use crate::store::Packet;
+use crate::view::Item;
+
+pub use rmp_serde::decode::Error as DecodeError;
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    // maps with field names and ids as strings, as in JSON
+    let mut serializer = rmp_serde::Serializer::new(&mut bytes)
+        .with_struct_map()
+        .with_human_readable();
+    value.serialize(&mut serializer).unwrap();
+    bytes
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let mut deserializer = rmp_serde::Deserializer::new(bytes).with_human_readable();
+    T::deserialize(&mut deserializer)
+}
+
+#[derive(Serialize, Deserialize)]
+struct WirePacket(
+    #[serde(
+        serialize_with = "crate::wire::serialize",
+        deserialize_with = "crate::wire::deserialize"
+    )]
+    Packet,
+);
+
+pub fn encode_packet(packet: &Packet) -> Vec<u8> {
+    encode(&WirePacket(packet.clone()))
+}
+
+pub fn decode_packet(bytes: &[u8]) -> Result<Packet, DecodeError> {
+    decode::<WirePacket>(bytes).map(|packet| packet.0)
+}
+
+pub fn encode_item(item: &Item) -> Vec<u8> {
+    encode(item)
+}
+
+pub fn decode_item(bytes: &[u8]) -> Result<Item, DecodeError> {
+    decode(bytes)
+}
+
+/// Encodes the results of `Store::search_items`.
+pub fn encode_results(results: &SearchResults<&Item>) -> Vec<u8> {
+    encode(results)
+}
+
+pub fn decode_results(bytes: &[u8]) -> Result<SearchResults<Item>, DecodeError> {
+    decode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::search::SearchQuery;
+    use crate::store::{MimeType, Store};
+    use crate::view::View;
+
+    fn as_json(bytes: &[u8]) -> serde_json::Value {
+        decode(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let stack = store
+            .add(b"Recipes", MimeType::TextPlain, None, Some("notes".into()))
+            .unwrap();
+        let item = store
+            .add(b"pancakes", MimeType::TextPlain, Some(stack.id()), None)
+            .unwrap();
+        let mut view = View::new();
+        store.scan().for_each(|packet| view.merge(packet.unwrap()));
+
+        let encoded = encode_packet(&stack);
+        assert_eq!(decode_packet(&encoded).unwrap(), stack);
+        assert_eq!(as_json(&encoded), crate::wire::to_value(&stack));
+        let json = serde_json::to_string(&crate::wire::to_value(&stack)).unwrap();
+        assert!(encoded.len() < json.len());
+
+        let stack = view.get(&stack.id()).unwrap();
+        let encoded = encode_item(stack);
+        assert_eq!(&decode_item(&encoded).unwrap(), stack);
+        assert_eq!(as_json(&encoded), serde_json::to_value(stack).unwrap());
+
+        let results = store.search_items(&SearchQuery::new().terms("pancakes"), &view);
+        let encoded = encode_results(&results);
+        let decoded = decode_results(&encoded).unwrap();
+        assert_eq!(decoded.hits, vec![view.get(&item.id()).unwrap().clone()]);
+        assert!(!decoded.truncated);
+        assert_eq!(as_json(&encoded), serde_json::to_value(&results).unwrap());
+
+        assert!(decode_packet(b"\x92\x01\x02").is_err());
+    }
+}
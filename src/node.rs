@@ -0,0 +1,245 @@
+//! Node.js bindings, built with napi-rs, see `package.json`, so Electron
+//! frontends can embed a store rather than talk to a daemon.
+//!
+//! ```js
+//! const { Store } = require("s2");
+//!
+//! const store = new Store("/path/to/store");
+//! const watcher = store.watch((err, packet) => console.log(packet));
+//! store.add("hello");
+//! store.search("hello"); // [{ score, id, hash }]
+//! watcher.stop();
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use napi::bindgen_prelude::Buffer;
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::JsFunction;
+use napi_derive::napi;
+use scru128::Scru128Id;
+
+use crate::source::Source;
+use crate::stacks::Stacks;
+use crate::store::{MimeType, StoreOptions};
+use crate::view::Item;
+
+fn parse_id(id: &str) -> napi::Result<Scru128Id> {
+    id.parse()
+        .map_err(|_| napi::Error::from_reason(format!("invalid id: {}", id)))
+}
+
+#[napi(object)]
+pub struct SearchHit {
+    pub score: f64,
+    pub id: String,
+    pub hash: String,
+}
+
+#[napi(object)]
+pub struct JsItem {
+    pub id: String,
+    pub hash: String,
+    pub stack_id: Option<String>,
+    pub children: Vec<String>,
+    /// The id of the packet that last touched the item.
+    pub last_touched: String,
+}
+
+impl From<&Item> for JsItem {
+    fn from(item: &Item) -> Self {
+        JsItem {
+            id: item.id.to_string(),
+            hash: item.hash.to_string(),
+            stack_id: item.stack_id.map(|id| id.to_string()),
+            children: item.children.iter().map(|id| id.to_string()).collect(),
+            last_touched: item.last_touched.to_string(),
+        }
+    }
+}
+
+#[napi(js_name = "Store")]
+pub struct JsStore {
+    stacks: Arc<Mutex<Stacks>>,
+}
+
+#[napi]
+impl JsStore {
+    #[napi(constructor)]
+    pub fn new(path: String) -> napi::Result<Self> {
+        let stacks = Stacks::try_open(&path, StoreOptions::default())
+            .map_err(|err| napi::Error::from_reason(err.to_string()))?;
+        Ok(JsStore {
+            stacks: Arc::new(Mutex::new(stacks)),
+        })
+    }
+
+    /// Adds an item, returning its id. A string is stored as UTF-8.
+    #[napi(
+        ts_args_type = "content: string | Buffer, mimeType?: string, stackId?: string, source?: string"
+    )]
+    pub fn add(
+        &self,
+        content: napi::Either<String, Buffer>,
+        mime_type: Option<String>,
+        stack_id: Option<String>,
+        source: Option<String>,
+    ) -> napi::Result<String> {
+        let content: &[u8] = match &content {
+            napi::Either::A(text) => text.as_bytes(),
+            napi::Either::B(buffer) => buffer,
+        };
+        self.add_bytes(content, mime_type, stack_id, source)
+    }
+
+    /// Items matching `query`, best first, see `Index::query`.
+    #[napi]
+    pub fn search(&self, query: String) -> Vec<SearchHit> {
+        let stacks = self.stacks.lock().unwrap();
+        let results = stacks.search(&query);
+        results
+            .into_iter()
+            .map(|(score, item)| SearchHit {
+                score: score.into(),
+                id: item.id.to_string(),
+                hash: item.hash.to_string(),
+            })
+            .collect()
+    }
+
+    /// The root items, or the children of `stack_id`.
+    #[napi]
+    pub fn list(&self, stack_id: Option<String>) -> napi::Result<Vec<JsItem>> {
+        let stack_id = stack_id.as_deref().map(parse_id).transpose()?;
+        let stacks = self.stacks.lock().unwrap();
+        Ok(stacks
+            .list(stack_id)
+            .into_iter()
+            .map(JsItem::from)
+            .collect())
+    }
+
+    #[napi]
+    pub fn content(&self, id: String) -> napi::Result<Option<Buffer>> {
+        Ok(self.content_bytes(&id)?.map(Buffer::from))
+    }
+
+    /// Calls `callback` with each packet written from now on, as its JSON,
+    /// see `wire`,
+    /// until the returned watcher is stopped.
+    #[napi(ts_args_type = "callback: (err: null | Error, packet: object) => void")]
+    pub fn watch(&self, callback: JsFunction) -> napi::Result<Watcher> {
+        let callback: ThreadsafeFunction<serde_json::Value, ErrorStrategy::CalleeHandled> =
+            callback.create_threadsafe_function(0, |ctx| Ok(vec![ctx.value]))?;
+        let mut follow = {
+            let stacks = self.stacks.lock().unwrap();
+            let store = stacks.store();
+            store.follow_after(store.latest_packet_id())
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let worker = std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                if let Some(packet) = follow.next_timeout(Duration::from_millis(100)) {
+                    let packet = packet
+                        .map(|packet| crate::wire::to_value(&packet))
+                        .map_err(|err| napi::Error::from_reason(err.to_string()));
+                    callback.call(packet, ThreadsafeFunctionCallMode::NonBlocking);
+                }
+            }
+        });
+        Ok(Watcher {
+            stop,
+            worker: Some(worker),
+        })
+    }
+}
+
+// what doesn't touch JS values, so tests can run without a JS runtime
+impl JsStore {
+    fn add_bytes(
+        &self,
+        content: &[u8],
+        mime_type: Option<String>,
+        stack_id: Option<String>,
+        source: Option<String>,
+    ) -> napi::Result<String> {
+        let mime_type = mime_type
+            .as_deref()
+            .map_or(MimeType::TextPlain, MimeType::from);
+        let stack_id = stack_id.as_deref().map(parse_id).transpose()?;
+        let packet = self
+            .stacks
+            .lock()
+            .unwrap()
+            .add(content, mime_type, stack_id, source.map(Source::new))
+            .map_err(|err| napi::Error::from_reason(err.to_string()))?;
+        Ok(packet.id().to_string())
+    }
+
+    fn content_bytes(&self, id: &str) -> napi::Result<Option<Vec<u8>>> {
+        self.stacks
+            .lock()
+            .unwrap()
+            .content(&parse_id(id)?)
+            .map_err(|err| napi::Error::from_reason(err.to_string()))
+    }
+}
+
+/// Calls a callback with packets as they're written, see `Store.watch`.
+#[napi]
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+#[napi]
+impl Watcher {
+    #[napi]
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            worker.join().unwrap();
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsStore::new(dir.path().to_str().unwrap().to_string()).unwrap();
+        let stack = store.add_bytes(b"Snippets", None, None, None).unwrap();
+        let item = store
+            .add_bytes(
+                b"let x = 1;",
+                Some("text/plain".to_string()),
+                Some(stack.clone()),
+                Some("editor".to_string()),
+            )
+            .unwrap();
+
+        let roots = store.list(None).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].children, vec![item.clone()]);
+        assert_eq!(store.list(Some(stack)).unwrap()[0].id, item);
+        let hits = store.search("let".to_string());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, item);
+        let content = store.content_bytes(&item).unwrap();
+        assert_eq!(content, Some(b"let x = 1;".to_vec()));
+        assert!(store.list(Some("nope".to_string())).is_err());
+    }
+}
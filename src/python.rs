@@ -0,0 +1,244 @@
+//! Python bindings, built with maturin, see `pyproject.toml`, for scripting
+//! over clipboard history, e.g. from a notebook:
+//!
+//! ```python
+//! import s2
+//!
+//! store = s2.Store("/path/to/store")
+//! view = store.view()
+//! for score, item in store.search("invoice", view):
+//!     print(score, store.content(item["hash"]))
+//! ```
+//!
+//! Packets and items are returned as dicts, shaped like their JSON, see
+//! `wire` for packets'.
+
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use scru128::Scru128Id;
+use serde::Serialize;
+
+use crate::source::Source;
+use crate::store::{MimeType, Store};
+use crate::view::View;
+
+fn parse_id(id: &str) -> PyResult<Scru128Id> {
+    id.parse()
+        .map_err(|_| PyValueError::new_err(format!("invalid id: {}", id)))
+}
+
+/// `value` as the Python value its JSON decodes to.
+fn to_python<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).unwrap();
+    let value = py.import("json")?.call_method1("loads", (json,))?;
+    Ok(value.unbind())
+}
+
+/// Content passed as `str` is stored as UTF-8.
+#[derive(FromPyObject)]
+enum Bytes {
+    Text(String),
+    Raw(Vec<u8>),
+}
+
+impl Bytes {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Bytes::Text(text) => text.as_bytes(),
+            Bytes::Raw(bytes) => bytes,
+        }
+    }
+}
+
+#[pyclass(name = "Store", unsendable)]
+pub struct PyStore {
+    store: Store,
+}
+
+#[pymethods]
+impl PyStore {
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        let store = Store::try_open(path, Default::default())
+            .map_err(|err| PyIOError::new_err(err.to_string()))?;
+        Ok(PyStore { store })
+    }
+
+    /// Adds an item, returning its id.
+    #[pyo3(signature = (content, mime_type = "text/plain", stack_id = None, source = None))]
+    fn add(
+        &mut self,
+        content: Bytes,
+        mime_type: &str,
+        stack_id: Option<&str>,
+        source: Option<&str>,
+    ) -> PyResult<String> {
+        let stack_id = stack_id.map(parse_id).transpose()?;
+        let packet = self
+            .store
+            .add(
+                content.as_bytes(),
+                MimeType::from(mime_type),
+                stack_id,
+                source.map(Source::new),
+            )
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(packet.id().to_string())
+    }
+
+    /// Changes an item's content, its stack, or both, returning the id of
+    /// the update.
+    #[pyo3(signature = (id, content = None, mime_type = "text/plain", stack_id = None))]
+    fn update(
+        &mut self,
+        id: &str,
+        content: Option<Bytes>,
+        mime_type: &str,
+        stack_id: Option<&str>,
+    ) -> PyResult<String> {
+        let stack_id = stack_id.map(parse_id).transpose()?;
+        let content = content.as_ref().map(Bytes::as_bytes);
+        let mime_type = MimeType::from(mime_type);
+        let packet = self
+            .store
+            .update(parse_id(id)?, content, mime_type, stack_id, None)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(packet.id().to_string())
+    }
+
+    fn delete(&mut self, id: &str) -> PyResult<String> {
+        let packet = self
+            .store
+            .delete(parse_id(id)?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(packet.id().to_string())
+    }
+
+    /// Every packet in the log, oldest first. Raises if one doesn't decode.
+    fn packets(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let packets = self
+            .store
+            .scan()
+            .map(|packet| packet.map(|packet| crate::wire::to_value(&packet)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| PyIOError::new_err(err.to_string()))?;
+        to_python(py, &packets)
+    }
+
+    fn content<'py>(&self, py: Python<'py>, hash: &str) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let hash = hash
+            .parse()
+            .map_err(|_| PyValueError::new_err(format!("invalid hash: {}", hash)))?;
+        let content = self
+            .store
+            .cas_read(&hash)
+            .map_err(|err| PyIOError::new_err(err.to_string()))?;
+        Ok(content.map(|content| PyBytes::new(py, &content)))
+    }
+
+    /// The items, as of now.
+    fn view(&self) -> PyView {
+        let mut view = View::new();
+        self.store
+            .scan()
+            .flatten()
+            .for_each(|packet| view.merge(packet));
+        PyView { view }
+    }
+
+    /// Items of `view` matching `query`, with their scores, best first.
+    fn search(&self, py: Python<'_>, query: &str, view: &PyView) -> PyResult<PyObject> {
+        to_python(py, &self.store.query_items(query, &view.view))
+    }
+}
+
+#[pyclass(name = "View", unsendable)]
+pub struct PyView {
+    view: View,
+}
+
+#[pymethods]
+impl PyView {
+    fn __len__(&self) -> usize {
+        self.view.items.len()
+    }
+
+    fn get(&self, py: Python<'_>, id: &str) -> PyResult<PyObject> {
+        to_python(py, &self.view.get(&parse_id(id)?))
+    }
+
+    /// Every item, oldest first.
+    fn items(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let mut items: Vec<_> = self.view.items.values().collect();
+        items.sort_by_key(|item| item.id);
+        to_python(py, &items)
+    }
+
+    /// The ids of the items without a stack.
+    fn root(&self) -> Vec<String> {
+        let roots = self.view.root_ids().into_iter();
+        roots.map(|id| id.to_string()).collect()
+    }
+
+    fn children(&self, id: &str) -> PyResult<Vec<String>> {
+        let children = match self.view.get(&parse_id(id)?) {
+            Some(item) => self.view.children(item),
+            None => Vec::new(),
+        };
+        Ok(children.iter().map(|id| id.to_string()).collect())
+    }
+}
+
+#[pymodule]
+fn s2(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyStore>()?;
+    m.add_class::<PyView>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pyo3::ffi::c_str;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_bindings() {
+        let dir = tempfile::tempdir().unwrap();
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let s2 = pyo3::wrap_pymodule!(s2)(py);
+            let locals = PyDict::new(py);
+            locals.set_item("s2", s2).unwrap();
+            locals
+                .set_item("path", dir.path().to_str().unwrap())
+                .unwrap();
+            let script = c_str!(
+                r#"
+store = s2.Store(path)
+stack = store.add("Bills")
+item = store.add(b"invoice 42", stack_id=stack, source="mail")
+store.update(item, content="invoice 43")
+view = store.view()
+assert len(view) == 2
+assert view.root() == [stack]
+assert view.children(stack) == [item]
+[(score, hit)] = store.search("invoice", view)
+assert hit["id"] == item
+assert store.content(hit["hash"]) == b"invoice 43"
+assert [p["data"]["source"] for p in store.packets() if p["type"] == "add"] == [None, {"app": "mail", "window_title": None, "device": None, "url": None, "path": None}]
+store.delete(item)
+assert store.view().get(item) is None
+try:
+    store.delete("not an id")
+    raise AssertionError("deleted")
+except ValueError:
+    pass
+"#
+            );
+            py.run(script, None, Some(&locals)).unwrap();
+        });
+    }
+}
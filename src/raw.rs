@@ -0,0 +1,472 @@
+//! Packets read in place from the log's bytes, see `Store::scan_raw`, for
+//! scans that look at a few fields, or skip most packets, without decoding
+//! an owned `Packet` for each. Rebuilding a view still decodes every packet,
+//! as items own what they hold.
+
+use scru128::Scru128Id;
+use serde::Deserialize;
+
+use crate::store::{Packet, PacketKind, Version};
+
+/// A packet as it's stored, sharing sled's buffer.
+#[derive(Clone)]
+pub struct RawPacket {
+    bytes: sled::IVec,
+}
+
+/// A packet's fields, borrowing its strings from the log, see
+/// `RawPacket::fields`. The accessors of `Packet` of the same names agree.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct PacketRef<'a> {
+    pub kind: PacketKind,
+    pub id: Scru128Id,
+    /// The item an update, fork, delete or access is of.
+    pub source_id: Option<Scru128Id>,
+    /// The content's hash, as the string `Integrity` parses from.
+    pub hash: Option<&'a str>,
+    pub stack_id: Option<Scru128Id>,
+    /// The source's `app`, still escaped if it holds an ASCII unit
+    /// separator or escape character, see `Source`.
+    pub app: Option<&'a str>,
+    pub device_id: Option<&'a str>,
+    pub clock: Option<u64>,
+}
+
+// Mirrors of the packets, up to the fields `PacketRef` has, with strings
+// borrowed. bincode reads fields in order, so the rest are left unread.
+// Nothing ties them to the packet structs at compile time: test_mirrors
+// fails once a packet's leading fields no longer match its mirror.
+
+#[derive(Deserialize)]
+enum Mirror<'a> {
+    #[serde(borrow)]
+    Add(AddMirror<'a>),
+    #[serde(borrow)]
+    Update(ChangeMirror<'a>),
+    #[serde(borrow)]
+    Fork(ChangeMirror<'a>),
+    #[serde(borrow)]
+    Delete(DeleteMirror<'a>),
+    #[serde(borrow)]
+    Snapshot(SnapshotMirror<'a>),
+    #[serde(borrow)]
+    Access(AccessMirror<'a>),
+    #[serde(borrow)]
+    Policy(PolicyMirror<'a>),
+}
+
+#[derive(Deserialize)]
+struct AddMirror<'a> {
+    id: Scru128Id,
+    hash: &'a str,
+    stack_id: Option<Scru128Id>,
+    source: Option<&'a str>,
+    device_id: Option<&'a str>,
+    clock: Option<u64>,
+}
+
+/// An update or a fork, which share their leading fields.
+#[derive(Deserialize)]
+struct ChangeMirror<'a> {
+    id: Scru128Id,
+    source_id: Scru128Id,
+    hash: Option<&'a str>,
+    stack_id: Option<Scru128Id>,
+    source: Option<&'a str>,
+    device_id: Option<&'a str>,
+    clock: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct DeleteMirror<'a> {
+    id: Scru128Id,
+    source_id: Scru128Id,
+    device_id: Option<&'a str>,
+    clock: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotMirror<'a> {
+    id: Scru128Id,
+    hash: &'a str,
+    stack_id: Option<Scru128Id>,
+    source: Option<&'a str>,
+    device_id: Option<&'a str>,
+    // only read to get to the version
+    _touched: Vec<Scru128Id>,
+    version: Version,
+}
+
+#[derive(Deserialize)]
+struct AccessMirror<'a> {
+    id: Scru128Id,
+    source_id: Scru128Id,
+    _kind: crate::store::AccessKind,
+    device_id: Option<&'a str>,
+    clock: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PolicyMirror<'a> {
+    id: Scru128Id,
+    _conflict_policy: crate::view::ConflictPolicy,
+    device_id: Option<&'a str>,
+    clock: Option<u64>,
+}
+
+/// Mirrors `LegacyPacket`.
+#[derive(Deserialize)]
+enum LegacyMirror<'a> {
+    Add {
+        id: Scru128Id,
+        hash: &'a str,
+        stack_id: Option<Scru128Id>,
+        source: Option<&'a str>,
+    },
+    Update {
+        id: Scru128Id,
+        source_id: Scru128Id,
+        hash: Option<&'a str>,
+        stack_id: Option<Scru128Id>,
+        source: Option<&'a str>,
+    },
+    Fork {
+        id: Scru128Id,
+        source_id: Scru128Id,
+        hash: Option<&'a str>,
+        stack_id: Option<Scru128Id>,
+        source: Option<&'a str>,
+    },
+    Delete {
+        id: Scru128Id,
+        source_id: Scru128Id,
+    },
+}
+
+impl<'a> LegacyMirror<'a> {
+    fn fields(self) -> PacketRef<'a> {
+        let (kind, id, source_id, hash, stack_id, source) = match self {
+            LegacyMirror::Add {
+                id,
+                hash,
+                stack_id,
+                source,
+            } => (PacketKind::Add, id, None, Some(hash), stack_id, source),
+            LegacyMirror::Update {
+                id,
+                source_id,
+                hash,
+                stack_id,
+                source,
+            } => (
+                PacketKind::Update,
+                id,
+                Some(source_id),
+                hash,
+                stack_id,
+                source,
+            ),
+            LegacyMirror::Fork {
+                id,
+                source_id,
+                hash,
+                stack_id,
+                source,
+            } => (
+                PacketKind::Fork,
+                id,
+                Some(source_id),
+                hash,
+                stack_id,
+                source,
+            ),
+            LegacyMirror::Delete { id, source_id } => {
+                (PacketKind::Delete, id, Some(source_id), None, None, None)
+            }
+        };
+        PacketRef {
+            kind,
+            id,
+            source_id,
+            hash,
+            stack_id,
+            app: source.map(crate::source::app_of),
+            device_id: None,
+            clock: None,
+        }
+    }
+}
+
+impl<'a> ChangeMirror<'a> {
+    fn fields(self, kind: PacketKind) -> PacketRef<'a> {
+        PacketRef {
+            kind,
+            id: self.id,
+            source_id: Some(self.source_id),
+            hash: self.hash,
+            stack_id: self.stack_id,
+            app: self.source.map(crate::source::app_of),
+            device_id: self.device_id,
+            clock: self.clock,
+        }
+    }
+}
+
+impl RawPacket {
+    pub(crate) fn new(bytes: sled::IVec) -> RawPacket {
+        RawPacket { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Read from the variant tag alone.
+    pub fn kind(&self) -> Option<PacketKind> {
+        PacketKind::from_encoded(&self.bytes)
+    }
+
+    /// The packet's fields, or `None` if it doesn't decode. As with
+    /// `decode_packet`, the current layout is tried before the legacy one.
+    pub fn fields(&self) -> Option<PacketRef<'_>> {
+        let Ok(mirror) = bincode::deserialize::<Mirror>(&self.bytes) else {
+            return bincode::deserialize::<LegacyMirror>(&self.bytes)
+                .ok()
+                .map(LegacyMirror::fields);
+        };
+        Some(match mirror {
+            Mirror::Add(packet) => PacketRef {
+                kind: PacketKind::Add,
+                id: packet.id,
+                source_id: None,
+                hash: Some(packet.hash),
+                stack_id: packet.stack_id,
+                app: packet.source.map(crate::source::app_of),
+                device_id: packet.device_id,
+                clock: packet.clock,
+            },
+            Mirror::Update(packet) => packet.fields(PacketKind::Update),
+            Mirror::Fork(packet) => packet.fields(PacketKind::Fork),
+            Mirror::Delete(packet) => PacketRef {
+                kind: PacketKind::Delete,
+                id: packet.id,
+                source_id: Some(packet.source_id),
+                hash: None,
+                stack_id: None,
+                app: None,
+                device_id: packet.device_id,
+                clock: packet.clock,
+            },
+            Mirror::Snapshot(packet) => PacketRef {
+                kind: PacketKind::Snapshot,
+                id: packet.id,
+                source_id: None,
+                hash: Some(packet.hash),
+                stack_id: packet.stack_id,
+                app: packet.source.map(crate::source::app_of),
+                device_id: packet.device_id,
+                clock: Some(packet.version.clock),
+            },
+            Mirror::Access(packet) => PacketRef {
+                kind: PacketKind::Access,
+                id: packet.id,
+                source_id: Some(packet.source_id),
+                hash: None,
+                stack_id: None,
+                app: None,
+                device_id: packet.device_id,
+                clock: packet.clock,
+            },
+            Mirror::Policy(packet) => PacketRef {
+                kind: PacketKind::Policy,
+                id: packet.id,
+                source_id: None,
+                hash: None,
+                stack_id: None,
+                app: None,
+                device_id: packet.device_id,
+                clock: packet.clock,
+            },
+        })
+    }
+
+    /// The owned packet, or `None` if it doesn't decode.
+    pub fn decode(&self) -> Option<Packet> {
+        crate::store::decode_packet(&self.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::source::Source;
+    use crate::store::{
+        AccessKind, AccessPacket, AddPacket, DeletePacket, ForkPacket, MimeType, PolicyPacket,
+        SnapshotPacket, Store, StoreOptions, UpdatePacket,
+    };
+    use crate::view::ConflictPolicy;
+
+    #[test]
+    fn test_mirrors() {
+        // every field set, and each to a value of its own, so a field
+        // inserted before a mirror's last one shifts what the mirror reads
+        let id = |n| Scru128Id::from_u128(n);
+        let hash = |content: &str| ssri::Integrity::from(content);
+        let source = Some(Source {
+            window_title: Some("Inbox".to_string()),
+            ..Source::new("mail")
+        });
+        let device_id = Some("laptop".to_string());
+        let packets = [
+            Packet::Add(AddPacket {
+                id: id(1),
+                hash: hash("add"),
+                stack_id: Some(id(2)),
+                source: source.clone(),
+                device_id: device_id.clone(),
+                clock: Some(3),
+                renditions: vec![hash("rendition")],
+            }),
+            Packet::Update(UpdatePacket {
+                id: id(4),
+                source_id: id(5),
+                hash: Some(hash("update")),
+                stack_id: Some(id(6)),
+                source: source.clone(),
+                device_id: device_id.clone(),
+                clock: Some(7),
+                base: Some(Version {
+                    clock: 8,
+                    id: id(9),
+                }),
+            }),
+            Packet::Fork(ForkPacket {
+                id: id(10),
+                source_id: id(11),
+                hash: Some(hash("fork")),
+                stack_id: Some(id(12)),
+                source: source.clone(),
+                device_id: device_id.clone(),
+                clock: Some(13),
+            }),
+            Packet::Delete(DeletePacket {
+                id: id(14),
+                source_id: id(15),
+                device_id: device_id.clone(),
+                clock: Some(16),
+            }),
+            Packet::Snapshot(SnapshotPacket {
+                id: id(17),
+                hash: hash("snapshot"),
+                stack_id: Some(id(18)),
+                source: source.clone(),
+                device_id: device_id.clone(),
+                touched: vec![id(17), id(19)],
+                version: Version {
+                    clock: 20,
+                    id: id(19),
+                },
+                renditions: vec![hash("rendition")],
+            }),
+            Packet::Access(AccessPacket {
+                id: id(21),
+                source_id: id(22),
+                kind: AccessKind::Executed,
+                device_id: device_id.clone(),
+                clock: Some(23),
+            }),
+            Packet::Policy(PolicyPacket {
+                id: id(24),
+                conflict_policy: ConflictPolicy::KeepBothAsFork,
+                device_id,
+                clock: Some(25),
+            }),
+        ];
+        for packet in packets {
+            let (source_id, stack_id) = match &packet {
+                Packet::Add(packet) => (None, packet.stack_id),
+                Packet::Update(packet) => (Some(packet.source_id), packet.stack_id),
+                Packet::Fork(packet) => (Some(packet.source_id), packet.stack_id),
+                Packet::Delete(packet) => (Some(packet.source_id), None),
+                Packet::Snapshot(packet) => (None, packet.stack_id),
+                Packet::Access(packet) => (Some(packet.source_id), None),
+                Packet::Policy(_) => (None, None),
+            };
+            let hash = packet.hash().map(|hash| hash.to_string());
+            let expected = PacketRef {
+                kind: packet.kind(),
+                id: packet.id(),
+                source_id,
+                hash: hash.as_deref(),
+                stack_id,
+                app: packet.source().map(|source| source.app.as_str()),
+                device_id: packet.device_id(),
+                clock: packet.clock(),
+            };
+            let raw = RawPacket::new(bincode::serialize(&packet).unwrap().into());
+            assert_eq!(raw.fields(), Some(expected));
+            assert_eq!(raw.decode(), Some(packet));
+        }
+    }
+
+    #[test]
+    fn test_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = StoreOptions {
+            device_id: Some("laptop".to_string()),
+            ..Default::default()
+        };
+        let mut store = Store::with_options(dir.path().to_str().unwrap(), options);
+        let source = Source {
+            window_title: Some("Inbox".to_string()),
+            ..Source::new("mail")
+        };
+        let stack = store
+            .add(b"Stack", MimeType::TextPlain, None, None)
+            .unwrap();
+        let item = store
+            .add(b"item", MimeType::TextPlain, None, Some(source))
+            .unwrap();
+        store
+            .update(
+                item.id(),
+                Some(b"edited"),
+                MimeType::TextPlain,
+                Some(stack.id()),
+                None,
+            )
+            .unwrap();
+        store
+            .fork(item.id(), None, MimeType::TextPlain, None, None)
+            .unwrap();
+        store.record_access(item.id(), AccessKind::Pasted);
+        store.delete(item.id()).unwrap();
+        let note = store.add(b"note", MimeType::TextPlain, None, None).unwrap();
+        store
+            .update(note.id(), Some(b"note 2"), MimeType::TextPlain, None, None)
+            .unwrap();
+        store.compact(1);
+
+        let packets: Vec<_> = store.scan_raw().collect();
+        assert_eq!(packets.len(), store.scan().count());
+        for raw in packets {
+            let packet = raw.decode().unwrap();
+            let fields = raw.fields().unwrap();
+            let hash = packet.hash().map(|hash| hash.to_string());
+            assert_eq!(raw.kind(), Some(packet.kind()));
+            assert_eq!(fields.kind, packet.kind());
+            assert_eq!(fields.id, packet.id());
+            assert_eq!(fields.hash, hash.as_deref());
+            assert_eq!(
+                fields.app,
+                packet.source().map(|source| source.app.as_str())
+            );
+            assert_eq!(fields.device_id, Some("laptop"));
+            assert_eq!(fields.clock, packet.clock());
+        }
+        assert!(store
+            .scan_raw()
+            .any(|raw| raw.kind() == Some(PacketKind::Snapshot)));
+    }
+}
@@ -0,0 +1,102 @@
+//! A store opened for inspection only, e.g. by a backup tool or a viewer.
+//! It has no methods that write, and opens no index writer.
+
+use std::path::Path;
+
+use std::ops::Deref;
+
+use crate::reader::StoreReader;
+use crate::store::{lock, open_error, open_sled, sled_config, Cas, Index, OpenError, StoreOptions};
+
+/// Reads like a `StoreReader`, which it derefs to.
+pub struct ReadOnlyStore {
+    reader: StoreReader,
+    /// Shared, so a writer can't open the store while it's being read.
+    _lock: Option<std::fs::File>,
+}
+
+impl ReadOnlyStore {
+    /// Opens the store at `path`, which must already exist and not be open
+    /// for writing. Protected content can't be opened, as a read-only store
+    /// has no keyring.
+    pub fn open(path: &str) -> Result<ReadOnlyStore, OpenError> {
+        ReadOnlyStore::open_with(path, &StoreOptions::default())
+    }
+
+    /// Opens the store with the log settings in `options`, see
+    /// `StoreBuilder::open_read_only`.
+    pub(crate) fn open_with(
+        path: &str,
+        options: &StoreOptions,
+    ) -> Result<ReadOnlyStore, OpenError> {
+        let path = Path::new(path);
+        for dir in ["sled", "index"] {
+            if !path.join(dir).is_dir() {
+                return Err(OpenError::Missing(path.to_path_buf()));
+            }
+        }
+        let lock = lock(path, false)?;
+        let config = sled_config(Some(path), options);
+        let db = match lock {
+            Some(_) => open_sled(path, &config)?,
+            None => config.open().map_err(|err| open_error(path, err))?,
+        };
+        let tree = |name: &str| db.open_tree(name).map_err(OpenError::Sled);
+
+        let reader = StoreReader::new(
+            tree("packets")?,
+            tree("content")?,
+            tree("chunks")?,
+            Cas::Dir(path.join("cas").into_os_string().into_string().unwrap()),
+            Index::open_read_only(&path.join("index")).map_err(OpenError::Index)?,
+        );
+        Ok(ReadOnlyStore {
+            reader,
+            _lock: lock,
+        })
+    }
+}
+
+impl Deref for ReadOnlyStore {
+    type Target = StoreReader;
+
+    fn deref(&self) -> &StoreReader {
+        &self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{MimeType, Store};
+
+    #[test]
+    fn test_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let missing = dir.path().join("missing");
+        assert!(matches!(
+            Store::open_read_only(missing.to_str().unwrap()),
+            Err(OpenError::Missing(_))
+        ));
+        assert!(!missing.exists());
+
+        let mut store = Store::new(path);
+        let packet = store
+            .add(b"read me", MimeType::TextPlain, None, None)
+            .unwrap();
+        drop(store);
+
+        let store = Store::open_read_only(path).unwrap();
+        assert_eq!(store.get_packet(&packet.id()), Ok(Some(packet.clone())));
+        assert_eq!(
+            store.cas_read(packet.hash().unwrap()).unwrap().unwrap(),
+            b"read me"
+        );
+        let view = store.view();
+        let results = store.query_items("read", &view);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1.id, packet.id());
+    }
+}
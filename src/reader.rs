@@ -0,0 +1,134 @@
+//! Cheap, cloneable handles reading a store while it's written, e.g. from a
+//! GUI thread searching while a capture thread adds, see `Store::reader`.
+
+use std::sync::Arc;
+
+use scru128::Scru128Id;
+use ssri::Integrity;
+
+use crate::store::{
+    blob_read, decode_content, get_packet, query_items, recorded_conflict_policy, scan, scan_after,
+    Cas, CasError, Content, Follow, Index, Packet, UndecodablePacket,
+};
+use crate::view::{ConflictPolicy, Item, View};
+
+/// Reads a store, sharing its log, CAS and index reader, so it sees each
+/// write as it's made. Clones share everything, and can be sent to other
+/// threads.
+///
+/// Protected content, confidential content sealed for the writer's session
+/// and ephemeral items, all of which need the writer's keys or memory, read
+/// as `None`. Corruption isn't recorded, unlike with `Store::cas_read`.
+#[derive(Clone)]
+pub struct StoreReader {
+    packets: sled::Tree,
+    content: sled::Tree,
+    chunks: sled::Tree,
+    cas: Cas,
+    /// Shares the writer's reader, without a writer of its own. Its options
+    /// are those of when the reader was made.
+    pub index: Arc<Index>,
+}
+
+impl StoreReader {
+    pub(crate) fn new(
+        packets: sled::Tree,
+        content: sled::Tree,
+        chunks: sled::Tree,
+        cas: Cas,
+        index: Index,
+    ) -> StoreReader {
+        StoreReader {
+            packets,
+            content,
+            chunks,
+            cas,
+            index: Arc::new(index),
+        }
+    }
+
+    pub fn get_packet(&self, id: &Scru128Id) -> Result<Option<Packet>, UndecodablePacket> {
+        get_packet(&self.packets, id)
+    }
+
+    /// See `Store::scan`.
+    pub fn scan(&self) -> impl Iterator<Item = Result<Packet, UndecodablePacket>> {
+        scan(&self.packets)
+    }
+
+    /// See `Store::scan_after`.
+    pub fn scan_after(
+        &self,
+        offset: Option<Scru128Id>,
+    ) -> impl Iterator<Item = Result<Packet, UndecodablePacket>> {
+        scan_after(&self.packets, offset)
+    }
+
+    /// See `Store::follow_after`.
+    pub fn follow_after(&self, offset: Option<Scru128Id>) -> Follow {
+        Follow::new(&self.packets, offset)
+    }
+
+    /// A view of the log, resolving conflicts with the policies recorded in
+    /// it.
+    pub fn view(&self) -> View {
+        let mut view = View::new();
+        self.scan().flatten().for_each(|packet| view.merge(packet));
+        view
+    }
+
+    /// See `Store::conflict_policy`.
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        recorded_conflict_policy(&self.packets)
+    }
+
+    pub fn content_meta(&self, hash: &Integrity) -> Option<Content> {
+        let bytes = bincode::serialize(&hash).unwrap();
+        self.content
+            .get(bytes)
+            .ok()
+            .flatten()
+            .and_then(|value| decode_content(&value))
+    }
+
+    /// Reads content. Protected content reads as `None`.
+    pub fn cas_read(&self, hash: &Integrity) -> Result<Option<Vec<u8>>, CasError> {
+        if self
+            .content_meta(hash)
+            .is_some_and(|meta| meta.protected_by.is_some())
+        {
+            return Ok(None);
+        }
+        blob_read(&self.cas, &self.chunks, hash)
+    }
+
+    pub fn query_items<'a>(&self, query: &str, view: &'a View) -> Vec<(f32, &'a Item)> {
+        query_items(&self.index, query, view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::store::{MimeType, Store};
+
+    #[test]
+    fn test_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let reader = store.reader();
+
+        // reads on another thread see writes as they're made
+        let mut follow = reader.clone().follow_after(None);
+        let searcher = std::thread::spawn(move || {
+            let packet = follow.next().unwrap().unwrap();
+            let view = reader.view();
+            let hits = reader.query_items("capture", &view);
+            assert_eq!(hits[0].1.id, packet.id());
+            reader.cas_read(packet.hash().unwrap()).unwrap().unwrap()
+        });
+        store
+            .add(b"a capture", MimeType::TextPlain, None, None)
+            .unwrap();
+        assert_eq!(searcher.join().unwrap(), b"a capture");
+    }
+}
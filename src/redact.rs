@@ -0,0 +1,180 @@
+use std::borrow::Cow;
+
+use regex::Regex;
+
+use crate::source::Source;
+use crate::store::MimeType;
+
+/// Rewrites text content before it's stored, see
+/// `Store::add_redaction_rule`.
+#[derive(Debug, Clone)]
+pub struct RedactionRule {
+    pub pattern: Regex,
+    /// Replaces each match; `$1` etc. refer to the pattern's groups.
+    pub replacement: String,
+    /// A glob on the source's `app`. Content without a source never matches
+    /// a rule with one.
+    pub source: Option<String>,
+    /// A glob on the MIME type, e.g. `text/*`.
+    pub mime_type: Option<String>,
+}
+
+impl RedactionRule {
+    pub fn new(pattern: &str, replacement: &str) -> Result<Self, regex::Error> {
+        Ok(RedactionRule {
+            pattern: Regex::new(pattern)?,
+            replacement: replacement.to_string(),
+            source: None,
+            mime_type: None,
+        })
+    }
+
+    pub fn for_source(mut self, glob: &str) -> Self {
+        self.source = Some(glob.to_string());
+        self
+    }
+
+    pub fn for_mime_type(mut self, glob: &str) -> Self {
+        self.mime_type = Some(glob.to_string());
+        self
+    }
+
+    /// Removes `utm_*` tracking parameters from URLs.
+    pub fn strip_utm() -> Self {
+        RedactionRule::new(r"(\?)(?:utm_[^&#\s]*&?)+|&utm_[^&#\s]*", "$1").unwrap()
+    }
+
+    pub fn mask_emails() -> Self {
+        RedactionRule::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}", "[email]").unwrap()
+    }
+
+    fn applies_to(&self, mime_type: &MimeType, source: Option<&Source>) -> bool {
+        let source_matches = match (&self.source, source) {
+            (None, _) => true,
+            (Some(glob), Some(source)) => glob_match(glob, &source.app),
+            (Some(_), None) => false,
+        };
+        source_matches
+            && self
+                .mime_type
+                .as_ref()
+                .is_none_or(|glob| glob_match(glob, mime_type.as_str()))
+    }
+}
+
+/// Applies the rules that apply to `mime_type` and `source`, in order. Only
+/// UTF-8 text is rewritten.
+pub(crate) fn redact<'a>(
+    rules: &[RedactionRule],
+    content: &'a [u8],
+    mime_type: &MimeType,
+    source: Option<&Source>,
+) -> Cow<'a, [u8]> {
+    let mut rules = rules
+        .iter()
+        .filter(|rule| rule.applies_to(mime_type, source))
+        .peekable();
+    if rules.peek().is_none() || !mime_type.is_text() {
+        return Cow::Borrowed(content);
+    }
+    let Ok(text) = std::str::from_utf8(content) else {
+        return Cow::Borrowed(content);
+    };
+
+    let mut text = Cow::Borrowed(text);
+    for rule in rules {
+        if let Cow::Owned(replaced) = rule.pattern.replace_all(&text, &rule.replacement) {
+            text = Cow::Owned(replaced);
+        }
+    }
+    match text {
+        Cow::Borrowed(_) => Cow::Borrowed(content),
+        Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+    }
+}
+
+/// Matches `*` to any run of characters and `?` to any one character.
+pub(crate) fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    // where the last `*` was, and how much of the text it has consumed
+    let mut star = None;
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, t));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_g, star_t)) => {
+                    g = star_g + 1;
+                    t = star_t + 1;
+                    star = Some((star_g, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let rules = vec![
+            RedactionRule::strip_utm(),
+            RedactionRule::mask_emails().for_source("*Mail*"),
+        ];
+        let apply = |content: &str, source: Option<&str>| {
+            let source = source.map(Source::new);
+            let redacted = redact(
+                &rules,
+                content.as_bytes(),
+                &MimeType::TextPlain,
+                source.as_ref(),
+            );
+            String::from_utf8(redacted.into_owned()).unwrap()
+        };
+
+        assert_eq!(
+            apply("https://a.com/p?utm_source=x&utm_medium=y&id=3", None),
+            "https://a.com/p?id=3"
+        );
+        assert_eq!(
+            apply("see https://a.com/p?id=3&utm_source=x today", None),
+            "see https://a.com/p?id=3 today"
+        );
+        assert_eq!(
+            apply("from jo@example.com", Some("Apple Mail")),
+            "from [email]"
+        );
+        // the email rule only applies to mail clients
+        assert_eq!(
+            apply("from jo@example.com", Some("Terminal")),
+            "from jo@example.com"
+        );
+        assert_eq!(apply("from jo@example.com", None), "from jo@example.com");
+
+        // binary content is left alone
+        let png = redact(&rules, b"?utm_x=1", &MimeType::ImagePng, None);
+        assert_eq!(png.as_ref(), b"?utm_x=1");
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("text/*", "text/plain"));
+        assert!(glob_match("*Mail*", "Apple Mail"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("text/*", "image/png"));
+        assert!(!glob_match("a*b", "acbc"));
+    }
+}
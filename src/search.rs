@@ -0,0 +1,290 @@
+//! Searches composed structurally, see `Store::search_items`, rather than
+//! written in the query language `Index::query` takes.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use scru128::Scru128Id;
+use serde::{Deserialize, Serialize};
+
+use crate::kind::ContentKind;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum SearchClause {
+    /// Each word, fuzzily, in the title or the content.
+    Terms(String),
+    /// The words, in order.
+    Phrase(String),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum Occur {
+    Must,
+    Should,
+    MustNot,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    /// Most recently indexed first, see `Index::query_recent`.
+    Newest,
+}
+
+/// A search. Filters of one kind are alternatives: content from either of
+/// two sources passes. Without `Must` clauses, `Should` clauses rank and
+/// narrow the matches; without either, every piece of content passing the
+/// filters matches.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SearchQuery {
+    pub(crate) clauses: Vec<(Occur, SearchClause)>,
+    pub(crate) sources: Vec<String>,
+    pub(crate) mime_types: Vec<String>,
+    pub(crate) kinds: Vec<ContentKind>,
+    pub(crate) stacks: Vec<Scru128Id>,
+    pub(crate) since: Option<u64>,
+    pub(crate) until: Option<u64>,
+    pub(crate) limit: usize,
+    pub(crate) sort: SearchSort,
+    pub(crate) include_history: bool,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) cancel: Option<CancelToken>,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        SearchQuery {
+            clauses: Vec::new(),
+            sources: Vec::new(),
+            mime_types: Vec::new(),
+            kinds: Vec::new(),
+            stacks: Vec::new(),
+            since: None,
+            until: None,
+            limit: 400,
+            sort: SearchSort::default(),
+            include_history: false,
+            timeout: None,
+            cancel: None,
+        }
+    }
+}
+
+impl SearchQuery {
+    pub fn new() -> Self {
+        SearchQuery::default()
+    }
+
+    /// Requires every word of `text`.
+    pub fn terms(self, text: &str) -> Self {
+        self.must(SearchClause::Terms(text.to_string()))
+    }
+
+    /// Requires the words of `text`, in order.
+    pub fn phrase(self, text: &str) -> Self {
+        self.must(SearchClause::Phrase(text.to_string()))
+    }
+
+    pub fn must(mut self, clause: SearchClause) -> Self {
+        self.clauses.push((Occur::Must, clause));
+        self
+    }
+
+    pub fn should(mut self, clause: SearchClause) -> Self {
+        self.clauses.push((Occur::Should, clause));
+        self
+    }
+
+    pub fn not(mut self, clause: SearchClause) -> Self {
+        self.clauses.push((Occur::MustNot, clause));
+        self
+    }
+
+    /// Content from the app, see `Source::app`.
+    pub fn source(mut self, app: &str) -> Self {
+        self.sources.push(app.to_string());
+        self
+    }
+
+    /// Content of the MIME type, e.g. `image/png`, or of any type under it,
+    /// e.g. `image`.
+    pub fn mime_type(mut self, mime_type: &str) -> Self {
+        self.mime_types.push(mime_type.to_string());
+        self
+    }
+
+    pub fn kind(mut self, kind: ContentKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Items in the stack.
+    pub fn stack(mut self, stack_id: Scru128Id) -> Self {
+        self.stacks.push(stack_id);
+        self
+    }
+
+    /// Content indexed at or after `millis`, since the epoch.
+    pub fn since(mut self, millis: u64) -> Self {
+        self.since = Some(millis);
+        self
+    }
+
+    /// Content indexed before `millis`, since the epoch.
+    pub fn until(mut self, millis: u64) -> Self {
+        self.until = Some(millis);
+        self
+    }
+
+    /// At most this many results. The default is 400.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn sort(mut self, sort: SearchSort) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Also matches content items were updated away from, returning the
+    /// item that was, e.g. to find what a note said before it was edited.
+    pub fn include_history(mut self) -> Self {
+        self.include_history = true;
+        self
+    }
+
+    /// Stops matching once the search has run this long, returning what
+    /// matched so far as truncated.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stops matching once `token` is cancelled, e.g. from the UI thread
+    /// when the user types on.
+    pub fn cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+}
+
+/// Cancels the searches it's given to, from any thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// What a search matched. `truncated` when it timed out or was cancelled
+/// before it had considered every match, in which case `hits` are the best
+/// of those it did.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResults<T> {
+    pub hits: Vec<T>,
+    pub truncated: bool,
+}
+
+/// When a search has to stop.
+#[derive(Clone)]
+pub(crate) struct Budget {
+    deadline: Option<Instant>,
+    cancel: Option<CancelToken>,
+    spent: Arc<AtomicBool>,
+}
+
+impl Budget {
+    pub(crate) fn new(query: &SearchQuery) -> Self {
+        Budget {
+            deadline: query.timeout.map(|timeout| Instant::now() + timeout),
+            cancel: query.cancel.clone(),
+            spent: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether the search has to stop, remembering that it did.
+    pub(crate) fn is_spent(&self) -> bool {
+        if self.spent.load(Ordering::Relaxed) {
+            return true;
+        }
+        let timed_out = self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        let cancelled = self.cancel.as_ref().is_some_and(CancelToken::is_cancelled);
+        if timed_out || cancelled {
+            self.spent.store(true, Ordering::Relaxed);
+        }
+        timed_out || cancelled
+    }
+
+    /// Limits a collector to matches found before the budget is spent.
+    pub(crate) fn limit<C>(&self, collector: C) -> Budgeted<C> {
+        Budgeted {
+            collector,
+            budget: self.clone(),
+        }
+    }
+}
+
+pub(crate) struct Budgeted<C> {
+    collector: C,
+    budget: Budget,
+}
+
+impl<C: tantivy::collector::Collector> tantivy::collector::Collector for Budgeted<C> {
+    type Fruit = C::Fruit;
+    type Child = Budgeted<C::Child>;
+
+    fn for_segment(
+        &self,
+        segment_local_id: tantivy::SegmentOrdinal,
+        segment: &tantivy::SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        let collector = self.collector.for_segment(segment_local_id, segment)?;
+        Ok(self.budget.limit(collector))
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.collector.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<Self::Child as tantivy::collector::SegmentCollector>::Fruit>,
+    ) -> tantivy::Result<Self::Fruit> {
+        self.collector.merge_fruits(segment_fruits)
+    }
+}
+
+impl<C: tantivy::collector::SegmentCollector> tantivy::collector::SegmentCollector for Budgeted<C> {
+    type Fruit = C::Fruit;
+
+    fn collect(&mut self, doc: tantivy::DocId, score: tantivy::Score) {
+        if !self.budget.is_spent() {
+            self.collector.collect(doc, score);
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.collector.harvest()
+    }
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Where a piece of content came from.
+///
+/// For human readable formats like JSON it serializes as a struct, and
+/// reads a bare string as the `app`. For others, i.e. the log's bincode, it
+/// serializes as a single string so packets written when `source` was a
+/// plain string still decode: a bare string is just the `app`. Other fields
+/// follow the app as `key=value` pairs, each preceded by an ASCII unit
+/// separator. The separator, and the escape character, are escaped in
+/// values.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub struct Source {
+    pub app: String,
+    pub window_title: Option<String>,
+    pub device: Option<String>,
+    pub url: Option<String>,
+    /// The file the content was read from, e.g. by `Store::import_dir`.
+    pub path: Option<String>,
+}
+
+const SEPARATOR: char = '\u{1f}';
+const ESCAPE: char = '\u{1b}';
+
+fn escape(value: &str, encoded: &mut String) {
+    for c in value.chars() {
+        match c {
+            SEPARATOR => encoded.extend([ESCAPE, 's']),
+            ESCAPE => encoded.extend([ESCAPE, 'e']),
+            c => encoded.push(c),
+        }
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            (ESCAPE, Some('s')) => unescaped.push(SEPARATOR),
+            (ESCAPE, Some('e')) => unescaped.push(ESCAPE),
+            // written before values were escaped
+            (c, _) => {
+                unescaped.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    unescaped
+}
+
+impl Source {
+    pub fn new(app: impl Into<String>) -> Self {
+        Source {
+            app: app.into(),
+            ..Default::default()
+        }
+    }
+
+    fn encode(&self) -> String {
+        let mut encoded = String::new();
+        escape(&self.app, &mut encoded);
+        for (key, value) in [
+            ("window_title", &self.window_title),
+            ("device", &self.device),
+            ("url", &self.url),
+            ("path", &self.path),
+        ] {
+            if let Some(value) = value {
+                encoded.push(SEPARATOR);
+                encoded.push_str(key);
+                encoded.push('=');
+                escape(value, &mut encoded);
+            }
+        }
+        encoded
+    }
+
+    fn decode(encoded: &str) -> Self {
+        let mut parts = encoded.split(SEPARATOR);
+        let mut source = Source::new(unescape(parts.next().unwrap_or_default()));
+        for part in parts {
+            let Some((key, value)) = part.split_once('=') else {
+                continue;
+            };
+            let value = Some(unescape(value));
+            match key {
+                "window_title" => source.window_title = value,
+                "device" => source.device = value,
+                "url" => source.url = value,
+                "path" => source.path = value,
+                // written by a newer version
+                _ => (),
+            }
+        }
+        source
+    }
+}
+
+impl From<&str> for Source {
+    fn from(app: &str) -> Self {
+        Source::new(app)
+    }
+}
+
+impl From<String> for Source {
+    fn from(app: String) -> Self {
+        Source::new(app)
+    }
+}
+
+/// The `app` of a source as it's encoded, without decoding the rest. An app
+/// holding the separator or escape character comes back escaped.
+pub(crate) fn app_of(encoded: &str) -> &str {
+    encoded.split(SEPARATOR).next().unwrap_or_default()
+}
+
+impl Serialize for Source {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            Source::serialize(self, serializer)
+        } else {
+            serializer.serialize_str(&self.encode())
+        }
+    }
+}
+
+/// A source in a human readable format: a struct, or a bare string written
+/// before it was one.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Readable {
+    App(String),
+    Fields(#[serde(with = "Source")] Source),
+}
+
+impl<'de> Deserialize<'de> for Source {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            Ok(match Readable::deserialize(deserializer)? {
+                Readable::App(app) => Source::new(app),
+                Readable::Fields(source) => source,
+            })
+        } else {
+            String::deserialize(deserializer).map(|encoded| Source::decode(&encoded))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_old_string_form() {
+        let old: Option<String> = Some("terminal".into());
+        let encoded = bincode::serialize(&old).unwrap();
+        let source: Option<Source> = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(source, Some(Source::new("terminal")));
+        // and an app-only source encodes exactly as before
+        assert_eq!(bincode::serialize(&source).unwrap(), encoded);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let source = Source {
+            app: "browser".into(),
+            window_title: Some("Docs = good".into()),
+            device: None,
+            url: Some("https://example.com/?a=b".into()),
+            path: Some("/tmp/notes.txt".into()),
+        };
+        let encoded = bincode::serialize(&source).unwrap();
+        assert_eq!(bincode::deserialize::<Source>(&encoded).unwrap(), source);
+
+        // the separator in a value doesn't split it
+        let source = Source {
+            app: "a\u{1f}b\u{1b}".into(),
+            window_title: Some("x\u{1f}path=y\u{1b}s".into()),
+            ..Default::default()
+        };
+        let encoded = bincode::serialize(&source).unwrap();
+        assert_eq!(bincode::deserialize::<Source>(&encoded).unwrap(), source);
+    }
+
+    #[test]
+    fn test_human_readable() {
+        let source = Source {
+            app: "browser".into(),
+            url: Some("https://example.com".into()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&source).unwrap();
+        assert_eq!(json["app"], "browser");
+        assert_eq!(json["url"], "https://example.com");
+        assert_eq!(serde_json::from_value::<Source>(json).unwrap(), source);
+        // as a bare string, it's the app
+        let old: Source = serde_json::from_str("\"terminal\"").unwrap();
+        assert_eq!(old, Source::new("terminal"));
+    }
+}
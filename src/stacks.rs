@@ -0,0 +1,332 @@
+use scru128::Scru128Id;
+
+use crate::reader::StoreReader;
+use crate::source::Source;
+use crate::store::{
+    AddError, CasError, InvalidOperation, MimeType, OpenError, Packet, Store, StoreOptions,
+};
+use crate::view::{Item, View};
+
+/// Owns a `Store` and keeps a `View` of it up to date, merging each packet
+/// the store writes through this type.
+pub struct Stacks {
+    store: Store,
+    view: View,
+}
+
+impl Stacks {
+    pub fn open(path: &str) -> Stacks {
+        Stacks::with_options(path, StoreOptions::default())
+    }
+
+    pub fn with_options(path: &str, options: StoreOptions) -> Stacks {
+        Stacks::try_open(path, options).unwrap()
+    }
+
+    /// Opens the store at `path`, see `Store::try_open`.
+    pub fn try_open(path: &str, options: StoreOptions) -> Result<Stacks, OpenError> {
+        let store = Store::try_open(path, options)?;
+        let mut view = View::new();
+        store.scan().flatten().for_each(|p| view.merge(p));
+        Ok(Stacks { store, view })
+    }
+
+    /// Keeps everything in memory, see `Store::ephemeral`.
+    #[cfg(target_os = "linux")]
+    pub fn ephemeral(options: StoreOptions) -> Stacks {
+        let store = Store::ephemeral(options);
+        Stacks {
+            store,
+            view: View::new(),
+        }
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// A handle reading the store from other threads, see `Store::reader`.
+    /// It builds views of its own.
+    pub fn reader(&self) -> StoreReader {
+        self.store.reader()
+    }
+
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+
+    fn apply(&mut self, packet: Packet) -> Packet {
+        self.view.merge(packet.clone());
+        packet
+    }
+
+    pub fn add(
+        &mut self,
+        content: &[u8],
+        mime_type: MimeType,
+        stack_id: Option<Scru128Id>,
+        source: Option<Source>,
+    ) -> Result<Packet, AddError> {
+        let packet = self.store.add(content, mime_type, stack_id, source)?;
+        // a stack `StoreOptions::auto_stack` added for it
+        if let Packet::Add(add) = &packet {
+            let added = add.stack_id.filter(|id| self.view.get(id).is_none());
+            if let Some(stack) = added.and_then(|id| self.store.get_packet(&id).ok().flatten()) {
+                self.view.merge(stack);
+            }
+        }
+        Ok(self.apply(packet))
+    }
+
+    pub fn update(
+        &mut self,
+        source_id: Scru128Id,
+        content: Option<&[u8]>,
+        mime_type: MimeType,
+        stack_id: Option<Scru128Id>,
+        source: Option<Source>,
+    ) -> Result<Packet, InvalidOperation> {
+        let latest = self.store.latest_packet_id();
+        let packet = self
+            .store
+            .update(source_id, content, mime_type, stack_id, source)?;
+        // updates sealing the items within one moved into a protected stack
+        for sealed in self.store.scan_after(latest).flatten() {
+            if sealed.id() != packet.id() {
+                self.view.merge(sealed);
+            }
+        }
+        Ok(self.apply(packet))
+    }
+
+    pub fn fork(
+        &mut self,
+        source_id: Scru128Id,
+        content: Option<&[u8]>,
+        mime_type: MimeType,
+        stack_id: Option<Scru128Id>,
+        source: Option<Source>,
+    ) -> Result<Packet, InvalidOperation> {
+        let packet = self
+            .store
+            .fork(source_id, content, mime_type, stack_id, source)?;
+        Ok(self.apply(packet))
+    }
+
+    /// Adds an item that's shown but never written, see
+    /// `Store::add_ephemeral`.
+    pub fn add_ephemeral(
+        &mut self,
+        content: &[u8],
+        mime_type: MimeType,
+        stack_id: Option<Scru128Id>,
+        source: Option<Source>,
+    ) -> Packet {
+        let packet = self
+            .store
+            .add_ephemeral(content, mime_type, stack_id, source);
+        self.apply(packet)
+    }
+
+    /// Deletes the item, or discards it if it's ephemeral, so nothing is
+    /// written about it.
+    pub fn delete(&mut self, source_id: Scru128Id) -> Result<Packet, InvalidOperation> {
+        let packet = match self.store.discard_ephemeral(source_id) {
+            Some(packet) => packet,
+            None => self.store.delete(source_id)?,
+        };
+        Ok(self.apply(packet))
+    }
+
+    /// Creates a new stack at the root, named by `name`.
+    pub fn add_stack(&mut self, name: &str) -> Result<Scru128Id, AddError> {
+        self.add(name.as_bytes(), MimeType::TextPlain, None, None)
+            .map(|packet| packet.id())
+    }
+
+    pub fn add_to_stack(
+        &mut self,
+        stack_id: Scru128Id,
+        content: &[u8],
+        mime_type: MimeType,
+        source: Option<Source>,
+    ) -> Result<Scru128Id, AddError> {
+        self.add(content, mime_type, Some(stack_id), source)
+            .map(|packet| packet.id())
+    }
+
+    /// Forks a stack along with each of its children, so the new stack holds
+    /// its own copies.
+    pub fn fork_stack(
+        &mut self,
+        stack_id: Scru128Id,
+        name: Option<&str>,
+    ) -> Result<Scru128Id, InvalidOperation> {
+        let stack = self
+            .view
+            .get(&stack_id)
+            .ok_or(InvalidOperation::UnknownStack(stack_id))?;
+        let children = self.view.children(stack);
+
+        let new_stack_id = self
+            .fork(
+                stack_id,
+                name.map(str::as_bytes),
+                MimeType::TextPlain,
+                None,
+                None,
+            )?
+            .id();
+        for child_id in children {
+            let mime_type = self
+                .view
+                .get(&child_id)
+                .and_then(|child| self.store.content_meta(&child.hash))
+                .map(|meta| meta.mime_type)
+                .unwrap_or(MimeType::TextPlain);
+            self.fork(child_id, None, mime_type, Some(new_stack_id), None)?;
+        }
+        Ok(new_stack_id)
+    }
+
+    /// The root items, or the children of `stack_id`, in the view's order.
+    pub fn list(&self, stack_id: Option<Scru128Id>) -> Vec<&Item> {
+        match stack_id {
+            None => self.view.iter_root().collect(),
+            Some(stack_id) => match self.view.get(&stack_id) {
+                Some(stack) => self.view.iter_children(stack).collect(),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    /// See `Store::flush`.
+    pub fn flush(&mut self) {
+        self.store.flush();
+    }
+
+    pub fn search(&self, query: &str) -> Vec<(f32, &Item)> {
+        self.store.query_items(query, &self.view)
+    }
+
+    pub fn content(&self, id: &Scru128Id) -> Result<Option<Vec<u8>>, CasError> {
+        match self.view.get(id) {
+            Some(item) => self.store.cas_read(&item.hash),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stacks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let mut stacks = Stacks::open(path);
+
+        let stack_id = stacks.add_stack("Stack 1").unwrap();
+        let item_id = stacks
+            .add_to_stack(stack_id, b"Item 1", MimeType::TextPlain, None)
+            .unwrap();
+        stacks
+            .add_to_stack(stack_id, b"Item 2", MimeType::TextPlain, None)
+            .unwrap();
+        stacks
+            .update(
+                item_id,
+                Some(b"Item 1 - updated"),
+                MimeType::TextPlain,
+                None,
+                None,
+            )
+            .unwrap();
+
+        let new_stack_id = stacks.fork_stack(stack_id, Some("Stack 2")).unwrap();
+
+        let contents = |items: Vec<&Item>| {
+            items
+                .into_iter()
+                .map(|item| stacks.content(&item.id).unwrap().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            contents(stacks.list(None)),
+            vec![b"Stack 1".to_vec(), b"Stack 2".to_vec()]
+        );
+        assert_eq!(
+            contents(stacks.list(Some(new_stack_id))),
+            vec![b"Item 1 - updated".to_vec(), b"Item 2".to_vec()]
+        );
+        // the new stack holds forks, not the originals
+        assert!(stacks
+            .list(Some(new_stack_id))
+            .iter()
+            .all(|item| item.stack_id == Some(new_stack_id) && item.id != item_id));
+
+        let results = stacks.search("updated");
+        assert_eq!(results.len(), 1);
+
+        // a reopened store rebuilds the same view
+        drop(stacks);
+        let stacks = Stacks::open(path);
+        assert_eq!(stacks.list(Some(new_stack_id)).len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = StoreOptions {
+            validate: true,
+            ..Default::default()
+        };
+        let mut stacks = Stacks::with_options(dir.path().to_str().unwrap(), options);
+        let missing = scru128::new();
+        let mime_type = MimeType::TextPlain;
+        assert_eq!(
+            stacks.update(missing, None, mime_type.clone(), None, None),
+            Err(InvalidOperation::UnknownItem(missing))
+        );
+        assert_eq!(
+            stacks.fork(missing, None, mime_type, None, None),
+            Err(InvalidOperation::UnknownItem(missing))
+        );
+        assert_eq!(
+            stacks.delete(missing),
+            Err(InvalidOperation::UnknownItem(missing))
+        );
+        assert_eq!(
+            stacks.fork_stack(missing, None),
+            Err(InvalidOperation::UnknownStack(missing))
+        );
+        assert_eq!(stacks.store().scan().count(), 0);
+    }
+
+    #[test]
+    fn test_ephemeral_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+
+        let mut stacks = Stacks::open(path);
+        let stack_id = stacks.add_stack("Codes").unwrap();
+        let code = stacks.add_ephemeral(b"492817", MimeType::TextPlain, Some(stack_id), None);
+        let code = code.id();
+        assert_eq!(stacks.list(Some(stack_id))[0].id, code);
+        assert_eq!(stacks.content(&code).unwrap(), Some(b"492817".to_vec()));
+        assert_eq!(stacks.store().scan().count(), 1);
+        assert!(stacks.search("492817").is_empty());
+
+        stacks.delete(code).unwrap();
+        assert!(stacks.list(Some(stack_id)).is_empty());
+        assert!(stacks.store().ephemeral_item(&code).is_none());
+        assert_eq!(stacks.store().scan().count(), 1);
+
+        stacks.add_ephemeral(b"hunter2", MimeType::TextPlain, None, None);
+        drop(stacks);
+        let stacks = Stacks::open(path);
+        assert_eq!(stacks.list(None).len(), 1);
+    }
+}
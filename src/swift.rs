@@ -0,0 +1,357 @@
+//! Swift bindings, built with uniffi, for a macOS menu-bar app. Generate the
+//! Swift side from the built library:
+//!
+//! ```text
+//! cargo build --release --features swift
+//! uniffi-bindgen generate --library target/release/libs2.dylib --language swift --out-dir swift
+//! ```
+//!
+//! `swift/S2+AppKit.swift` adds an `AsyncStream` of packets and `NSImage`
+//! accessors on top of what's generated.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt;
+use scru128::Scru128Id;
+
+use crate::source::Source;
+use crate::stacks::Stacks;
+use crate::store::MimeType;
+use crate::view;
+
+#[derive(PartialEq, Debug, uniffi::Error)]
+pub enum StoreError {
+    Open { message: String },
+    InvalidId { id: String },
+    Add { message: String },
+    Read { message: String },
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Open { message } => write!(f, "couldn't open store: {}", message),
+            StoreError::InvalidId { id } => write!(f, "invalid id: {}", id),
+            StoreError::Add { message } => write!(f, "couldn't add: {}", message),
+            StoreError::Read { message } => write!(f, "couldn't read content: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+fn parse_id(id: &str) -> Result<Scru128Id, StoreError> {
+    id.parse()
+        .map_err(|_| StoreError::InvalidId { id: id.to_string() })
+}
+
+/// Runs `f` on a thread of its own, so awaiting it doesn't hold up the
+/// caller's executor, e.g. the main actor.
+async fn off_thread<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.await.unwrap()
+}
+
+#[derive(PartialEq, Debug, Clone, uniffi::Record)]
+pub struct Packet {
+    pub id: String,
+    /// One of `add`, `update`, `fork`, `delete`, `snapshot` or `access`.
+    pub kind: String,
+    /// The item an update, fork, delete or access is of.
+    pub source_id: Option<String>,
+    pub hash: Option<String>,
+    pub stack_id: Option<String>,
+    pub source: Option<String>,
+}
+
+impl From<&crate::store::Packet> for Packet {
+    fn from(packet: &crate::store::Packet) -> Self {
+        use crate::store::Packet::*;
+        let (kind, source_id, stack_id) = match packet {
+            Add(packet) => ("add", None, packet.stack_id),
+            Update(packet) => ("update", Some(packet.source_id), packet.stack_id),
+            Fork(packet) => ("fork", Some(packet.source_id), packet.stack_id),
+            Delete(packet) => ("delete", Some(packet.source_id), None),
+            Snapshot(packet) => ("snapshot", None, packet.stack_id),
+            Access(packet) => ("access", Some(packet.source_id), None),
+            Policy(_) => ("policy", None, None),
+        };
+        Packet {
+            id: packet.id().to_string(),
+            kind: kind.to_string(),
+            source_id: source_id.map(|id| id.to_string()),
+            hash: packet.hash().map(|hash| hash.to_string()),
+            stack_id: stack_id.map(|id| id.to_string()),
+            source: packet.source().map(|source| source.app.clone()),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, uniffi::Record)]
+pub struct Item {
+    pub id: String,
+    pub hash: String,
+    pub stack_id: Option<String>,
+    pub children: Vec<String>,
+    pub mime_type: String,
+    /// A short preview, see `Content::terse`.
+    pub terse: String,
+}
+
+#[derive(PartialEq, Debug, uniffi::Record)]
+pub struct SearchHit {
+    pub score: f32,
+    pub item: Item,
+}
+
+/// An image's content, for `NSImage(data:)`.
+#[derive(PartialEq, Debug, uniffi::Record)]
+pub struct Image {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+}
+
+#[derive(uniffi::Object)]
+pub struct Store {
+    stacks: Arc<Mutex<Stacks>>,
+}
+
+impl Store {
+    fn item(stacks: &Stacks, item: &view::Item) -> Item {
+        let meta = stacks.store().content_meta(&item.hash);
+        Item {
+            id: item.id.to_string(),
+            hash: item.hash.to_string(),
+            stack_id: item.stack_id.map(|id| id.to_string()),
+            children: item.children.iter().map(|id| id.to_string()).collect(),
+            mime_type: meta
+                .as_ref()
+                .map_or("text/plain", |meta| meta.mime_type.as_str())
+                .to_string(),
+            terse: meta.map(|meta| meta.terse).unwrap_or_default(),
+        }
+    }
+}
+
+#[uniffi::export]
+impl Store {
+    #[uniffi::constructor]
+    pub fn new(path: String) -> Result<Arc<Self>, StoreError> {
+        let stacks =
+            Stacks::try_open(&path, Default::default()).map_err(|err| StoreError::Open {
+                message: err.to_string(),
+            })?;
+        Ok(Arc::new(Store {
+            stacks: Arc::new(Mutex::new(stacks)),
+        }))
+    }
+
+    /// Adds an item, returning the packet that added it.
+    pub async fn add(
+        &self,
+        content: Vec<u8>,
+        mime_type: String,
+        stack_id: Option<String>,
+        source: Option<String>,
+    ) -> Result<Packet, StoreError> {
+        let stack_id = stack_id.as_deref().map(parse_id).transpose()?;
+        let stacks = self.stacks.clone();
+        off_thread(move || {
+            let packet = stacks.lock().unwrap().add(
+                &content,
+                MimeType::from(mime_type.as_str()),
+                stack_id,
+                source.map(Source::new),
+            );
+            packet
+                .map(|packet| (&packet).into())
+                .map_err(|err| StoreError::Add {
+                    message: err.to_string(),
+                })
+        })
+        .await
+    }
+
+    /// Items matching `query`, best first, see `Index::query`.
+    pub async fn search(&self, query: String) -> Vec<SearchHit> {
+        let stacks = self.stacks.clone();
+        off_thread(move || {
+            let stacks = stacks.lock().unwrap();
+            let results = stacks.search(&query);
+            results
+                .into_iter()
+                .map(|(score, item)| SearchHit {
+                    score,
+                    item: Store::item(&stacks, item),
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// The root items, or the children of `stack_id`.
+    pub fn list(&self, stack_id: Option<String>) -> Result<Vec<Item>, StoreError> {
+        let stack_id = stack_id.as_deref().map(parse_id).transpose()?;
+        let stacks = self.stacks.lock().unwrap();
+        let items = stacks.list(stack_id).into_iter();
+        Ok(items.map(|item| Store::item(&stacks, item)).collect())
+    }
+
+    pub fn content(&self, id: String) -> Result<Option<Vec<u8>>, StoreError> {
+        let id = parse_id(&id)?;
+        let stacks = self.stacks.lock().unwrap();
+        stacks.content(&id).map_err(|err| StoreError::Read {
+            message: err.to_string(),
+        })
+    }
+
+    /// The item's content if it's an image. With the `image` feature, WebP,
+    /// which NSImage only reads from macOS 11, is converted to PNG.
+    pub fn image(&self, id: String) -> Result<Option<Image>, StoreError> {
+        let id = parse_id(&id)?;
+        let stacks = self.stacks.lock().unwrap();
+        let Some(item) = stacks.view().get(&id) else {
+            return Ok(None);
+        };
+        let Some(meta) = stacks.store().content_meta(&item.hash) else {
+            return Ok(None);
+        };
+        if !meta.mime_type.as_str().starts_with("image/") {
+            return Ok(None);
+        }
+        let read_error = |message: String| StoreError::Read { message };
+        #[cfg(feature = "image")]
+        if meta.mime_type.as_str() == "image/webp" {
+            let data = stacks
+                .store()
+                .cas_read_as(&item.hash, &MimeType::ImagePng)
+                .map_err(|err| read_error(err.to_string()))?;
+            return Ok(data.map(|data| Image {
+                data,
+                mime_type: MimeType::ImagePng.as_str().to_string(),
+            }));
+        }
+        let data = stacks
+            .store()
+            .cas_read(&item.hash)
+            .map_err(|err| read_error(err.to_string()))?;
+        Ok(data.map(|data| Image {
+            data,
+            mime_type: meta.mime_type.as_str().to_string(),
+        }))
+    }
+
+    /// Packets as they're written from now on, see `S2+AppKit.swift` for
+    /// them as an `AsyncStream`.
+    pub fn watch(&self) -> Arc<PacketStream> {
+        let mut follow = {
+            let stacks = self.stacks.lock().unwrap();
+            let store = stacks.store();
+            store.follow_after(store.latest_packet_id())
+        };
+        let (tx, rx) = mpsc::unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let worker = std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                let Some(Ok(packet)) = follow.next_timeout(Duration::from_millis(100)) else {
+                    continue;
+                };
+                if tx.unbounded_send((&packet).into()).is_err() {
+                    break;
+                }
+            }
+        });
+        Arc::new(PacketStream {
+            packets: futures::lock::Mutex::new(rx),
+            stop,
+            worker: Mutex::new(Some(worker)),
+        })
+    }
+}
+
+/// Packets as they're written, see `Store::watch`.
+#[derive(uniffi::Object)]
+pub struct PacketStream {
+    packets: futures::lock::Mutex<mpsc::UnboundedReceiver<Packet>>,
+    stop: Arc<AtomicBool>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[uniffi::export]
+impl PacketStream {
+    /// The next packet written, or none once the stream is closed.
+    pub async fn next(&self) -> Option<Packet> {
+        self.packets.lock().await.next().await
+    }
+
+    pub fn close(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            worker.join().unwrap();
+        }
+    }
+}
+
+impl Drop for PacketStream {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::new(dir.path().to_str().unwrap().to_string()).unwrap();
+        let packets = store.watch();
+        let text = "text/plain".to_string();
+
+        let stack = block_on(store.add(b"Screenshots".to_vec(), text.clone(), None, None)).unwrap();
+        let png = b"\x89PNG not really".to_vec();
+        let image = block_on(store.add(
+            png.clone(),
+            "image/png".to_string(),
+            Some(stack.id.clone()),
+            Some("Preview".to_string()),
+        ))
+        .unwrap();
+        assert_eq!(image.kind, "add");
+        assert_eq!(image.source.as_deref(), Some("Preview"));
+        assert_eq!(block_on(packets.next()), Some(stack.clone()));
+        assert_eq!(block_on(packets.next()), Some(image.clone()));
+
+        let items = store.list(Some(stack.id.clone())).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].mime_type, "image/png");
+        let hits = block_on(store.search("screenshots".to_string()));
+        assert_eq!(hits[0].item.id, stack.id);
+        assert_eq!(
+            store.image(image.id).unwrap(),
+            Some(Image {
+                data: png,
+                mime_type: "image/png".to_string()
+            })
+        );
+        assert_eq!(store.image(stack.id).unwrap(), None);
+        assert!(matches!(
+            store.list(Some("nope".to_string())),
+            Err(StoreError::InvalidId { .. })
+        ));
+
+        packets.close();
+        assert_eq!(block_on(packets.next()), None);
+    }
+}
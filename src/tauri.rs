@@ -0,0 +1,279 @@
+//! Glue between a store and a Tauri app's webview: commands for each
+//! operation and an emitter forwarding packets as events. Packets reach the
+//! webview in the wire format, see `crate::wire`, from either.
+//!
+//! `tauri_commands!` defines the commands in the app's crate, where `tauri`
+//! is a dependency, so this crate doesn't need one. `examples/tauri` builds
+//! them against tauri's mock runtime:
+//!
+//! ```ignore
+//! s2::tauri_commands!();
+//!
+//! let stacks = s2::Stacks::open(path);
+//! tauri::Builder::default()
+//!     .setup(move |app| {
+//!         let handle = app.handle().clone();
+//!         let forwarder = s2::tauri::forward_packets(stacks.store(), move |packet| {
+//!             let _ = handle.emit(s2::tauri::PACKET_EVENT, packet);
+//!         });
+//!         app.manage(forwarder);
+//!         app.manage(std::sync::Mutex::new(stacks));
+//!         Ok(())
+//!     })
+//!     .invoke_handler(s2::tauri_handler!())
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+pub use scru128::Scru128Id;
+pub use serde_json::Value;
+
+use crate::source::Source;
+use crate::stacks::Stacks;
+use crate::store::{MimeType, Packet, Store};
+use crate::view::Item;
+
+/// The state the commands expect the app to manage.
+pub type SharedStacks = Mutex<Stacks>;
+
+/// The event `forward_packets` is used to emit packets as.
+pub const PACKET_EVENT: &str = "s2://packet";
+
+pub fn add(
+    stacks: &SharedStacks,
+    content: &str,
+    mime_type: Option<&str>,
+    stack_id: Option<Scru128Id>,
+    source: Option<String>,
+) -> Result<Packet, String> {
+    let mime_type = mime_type.map_or(MimeType::TextPlain, MimeType::from);
+    stacks
+        .lock()
+        .unwrap()
+        .add(
+            content.as_bytes(),
+            mime_type,
+            stack_id,
+            source.map(Source::new),
+        )
+        .map_err(|err| err.to_string())
+}
+
+/// Changes an item's content, its stack, or both, in place, or as a new
+/// item with `fork`.
+pub fn change(
+    stacks: &SharedStacks,
+    id: Scru128Id,
+    content: Option<&str>,
+    stack_id: Option<Scru128Id>,
+    fork: bool,
+) -> Result<Packet, String> {
+    let mut stacks = stacks.lock().unwrap();
+    if stacks.view().get(&id).is_none() {
+        return Err(format!("no item {}", id));
+    }
+    if let Some(stack_id) = stack_id.filter(|id| stacks.view().get(id).is_none()) {
+        return Err(format!("no stack {}", stack_id));
+    }
+    let content = content.map(str::as_bytes);
+    let mime_type = MimeType::TextPlain;
+    match fork {
+        true => stacks.fork(id, content, mime_type, stack_id, None),
+        false => stacks.update(id, content, mime_type, stack_id, None),
+    }
+    .map_err(|err| err.to_string())
+}
+
+pub fn delete(stacks: &SharedStacks, id: Scru128Id) -> Result<Packet, String> {
+    let mut stacks = stacks.lock().unwrap();
+    if stacks.view().get(&id).is_none() {
+        return Err(format!("no item {}", id));
+    }
+    stacks.delete(id).map_err(|err| err.to_string())
+}
+
+/// The root items, or the children of `stack_id`, see `Stacks::list`.
+pub fn list(stacks: &SharedStacks, stack_id: Option<Scru128Id>) -> Vec<Item> {
+    let stacks = stacks.lock().unwrap();
+    stacks.list(stack_id).into_iter().cloned().collect()
+}
+
+pub fn search(stacks: &SharedStacks, query: &str) -> Vec<(f32, Item)> {
+    let stacks = stacks.lock().unwrap();
+    let results = stacks.search(query);
+    results
+        .into_iter()
+        .map(|(score, item)| (score, item.clone()))
+        .collect()
+}
+
+pub fn content(stacks: &SharedStacks, id: Scru128Id) -> Result<Option<Vec<u8>>, String> {
+    stacks
+        .lock()
+        .unwrap()
+        .content(&id)
+        .map_err(|err| err.to_string())
+}
+
+/// Calls `emit` with each packet written after it's started, in the wire
+/// format, from a thread of its own, until it's dropped. Drop it before the
+/// store.
+pub fn forward_packets(
+    store: &Store,
+    mut emit: impl FnMut(Value) + Send + 'static,
+) -> PacketForwarder {
+    let mut follow = store.follow_after(store.latest_packet_id());
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let worker = std::thread::spawn(move || {
+        while !stopped.load(Ordering::Relaxed) {
+            if let Some(Ok(packet)) = follow.next_timeout(Duration::from_millis(100)) {
+                emit(crate::wire::to_value(&packet));
+            }
+        }
+    });
+    PacketForwarder {
+        stop,
+        worker: Some(worker),
+    }
+}
+
+/// Forwards packets, see `forward_packets`.
+pub struct PacketForwarder {
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Drop for PacketForwarder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            worker.join().unwrap();
+        }
+    }
+}
+
+/// Defines a Tauri command for each function of `s2::tauri`, named with an
+/// `s2_` prefix, taking the app's `SharedStacks` state. Packets are returned
+/// in the wire format. Register them with `tauri_handler!`.
+#[macro_export]
+macro_rules! tauri_commands {
+    () => {
+        #[::tauri::command]
+        fn s2_add(
+            stacks: ::tauri::State<'_, $crate::tauri::SharedStacks>,
+            content: String,
+            mime_type: Option<String>,
+            stack_id: Option<$crate::tauri::Scru128Id>,
+            source: Option<String>,
+        ) -> Result<$crate::tauri::Value, String> {
+            $crate::tauri::add(&stacks, &content, mime_type.as_deref(), stack_id, source)
+                .map(|packet| $crate::wire::to_value(&packet))
+        }
+
+        #[::tauri::command]
+        fn s2_update(
+            stacks: ::tauri::State<'_, $crate::tauri::SharedStacks>,
+            id: $crate::tauri::Scru128Id,
+            content: Option<String>,
+            stack_id: Option<$crate::tauri::Scru128Id>,
+        ) -> Result<$crate::tauri::Value, String> {
+            $crate::tauri::change(&stacks, id, content.as_deref(), stack_id, false)
+                .map(|packet| $crate::wire::to_value(&packet))
+        }
+
+        #[::tauri::command]
+        fn s2_fork(
+            stacks: ::tauri::State<'_, $crate::tauri::SharedStacks>,
+            id: $crate::tauri::Scru128Id,
+            content: Option<String>,
+            stack_id: Option<$crate::tauri::Scru128Id>,
+        ) -> Result<$crate::tauri::Value, String> {
+            $crate::tauri::change(&stacks, id, content.as_deref(), stack_id, true)
+                .map(|packet| $crate::wire::to_value(&packet))
+        }
+
+        #[::tauri::command]
+        fn s2_delete(
+            stacks: ::tauri::State<'_, $crate::tauri::SharedStacks>,
+            id: $crate::tauri::Scru128Id,
+        ) -> Result<$crate::tauri::Value, String> {
+            $crate::tauri::delete(&stacks, id).map(|packet| $crate::wire::to_value(&packet))
+        }
+
+        #[::tauri::command]
+        fn s2_list(
+            stacks: ::tauri::State<'_, $crate::tauri::SharedStacks>,
+            stack_id: Option<$crate::tauri::Scru128Id>,
+        ) -> Vec<$crate::Item> {
+            $crate::tauri::list(&stacks, stack_id)
+        }
+
+        #[::tauri::command]
+        fn s2_search(
+            stacks: ::tauri::State<'_, $crate::tauri::SharedStacks>,
+            query: String,
+        ) -> Vec<(f32, $crate::Item)> {
+            $crate::tauri::search(&stacks, &query)
+        }
+
+        #[::tauri::command]
+        fn s2_content(
+            stacks: ::tauri::State<'_, $crate::tauri::SharedStacks>,
+            id: $crate::tauri::Scru128Id,
+        ) -> Result<Option<Vec<u8>>, String> {
+            $crate::tauri::content(&stacks, id)
+        }
+    };
+}
+
+/// The invoke handler for the commands `tauri_commands!` defines.
+#[macro_export]
+macro_rules! tauri_handler {
+    () => {
+        ::tauri::generate_handler![
+            s2_add, s2_update, s2_fork, s2_delete, s2_list, s2_search, s2_content
+        ]
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let stacks = Stacks::open(dir.path().to_str().unwrap());
+        let (tx, rx) = std::sync::mpsc::channel();
+        let forwarder = forward_packets(stacks.store(), move |packet| {
+            tx.send(packet).unwrap();
+        });
+        let stacks: SharedStacks = Mutex::new(stacks);
+
+        let stack = add(&stacks, "Notes", None, None, None).unwrap();
+        let note = add(&stacks, "a note", None, Some(stack.id()), None).unwrap();
+        let updated = change(&stacks, note.id(), Some("an edited note"), None, false).unwrap();
+        assert_eq!(list(&stacks, None)[0].id, stack.id());
+        assert_eq!(list(&stacks, Some(stack.id()))[0].id, note.id());
+        assert_eq!(search(&stacks, "edited")[0].1.id, note.id());
+        assert_eq!(
+            content(&stacks, note.id()).unwrap(),
+            Some(b"an edited note".to_vec())
+        );
+        assert!(delete(&stacks, updated.id()).is_err());
+
+        let timeout = Duration::from_secs(5);
+        let forwarded: Vec<Value> = (0..3).map(|_| rx.recv_timeout(timeout).unwrap()).collect();
+        let expected: Vec<Value> = [stack, note, updated]
+            .iter()
+            .map(crate::wire::to_value)
+            .collect();
+        assert_eq!(forwarded, expected);
+        assert_eq!(forwarded[0]["type"], "add");
+        drop(forwarder);
+    }
+}
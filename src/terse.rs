@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+
+/// Controls how the `terse` preview stored alongside each piece of content is
+/// derived from it.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TerseOptions {
+    /// Maximum length of the preview in chars. `None` keeps the full text.
+    pub max_chars: Option<usize>,
+    /// Only use the first non-blank line.
+    pub first_line_only: bool,
+    /// When truncating, keep both ends of the text around an ellipsis rather
+    /// than just the start.
+    pub middle_ellipsis: bool,
+}
+
+impl Default for TerseOptions {
+    fn default() -> Self {
+        TerseOptions {
+            max_chars: Some(400),
+            first_line_only: false,
+            middle_ellipsis: false,
+        }
+    }
+}
+
+impl TerseOptions {
+    pub fn generate(&self, content: &[u8]) -> String {
+        let text = String::from_utf8_lossy(content);
+        let text = if self.first_line_only {
+            text.lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .unwrap_or("")
+        } else {
+            &text
+        };
+
+        match self.max_chars {
+            Some(max) if text.chars().count() > max => truncate(text, max, self.middle_ellipsis),
+            _ => text.to_string(),
+        }
+    }
+}
+
+/// A structured text format, see `pretty_print`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum StructuredFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+/// If `text` is a JSON, TOML or YAML document, its format and a
+/// pretty-printed rendering to generate the terse from, so a one-line blob
+/// previews as indented lines. A YAML document must span several lines and
+/// hold a mapping or sequence, as most text parses as a YAML string.
+pub fn pretty_print(text: &str) -> Option<(StructuredFormat, String)> {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(text) {
+            return Some((
+                StructuredFormat::Json,
+                serde_json::to_string_pretty(&value).unwrap(),
+            ));
+        }
+    }
+    if let Ok(table) = text.parse::<toml::Table>() {
+        if !table.is_empty() {
+            return Some((StructuredFormat::Toml, toml::to_string_pretty(&table).ok()?));
+        }
+    }
+    if text.trim().lines().count() > 1 {
+        if let Ok(value) = serde_yaml::from_str::<serde_yaml::Value>(text) {
+            if value.is_mapping() || value.is_sequence() {
+                return Some((StructuredFormat::Yaml, serde_yaml::to_string(&value).ok()?));
+            }
+        }
+    }
+    None
+}
+
+/// A human summary for PNG content, e.g. "PNG · 800×600 · 1.2 MB". The
+/// dimensions are omitted if the header can't be decoded.
+pub fn png_summary(content: &[u8]) -> String {
+    let size = human_size(content.len());
+    match png_dimensions(content) {
+        Some((width, height)) => format!("PNG · {}×{} · {}", width, height, size),
+        None => format!("PNG · {}", size),
+    }
+}
+
+/// A summary for content there's no textual preview for, e.g.
+/// "application/pdf · 1.2 MB".
+pub fn binary_summary(mime_type: &str, content: &[u8]) -> String {
+    format!("{} · {}", mime_type, human_size(content.len()))
+}
+
+fn png_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    // the IHDR chunk must come first: length, type, width, height
+    if content.len() < 24 || &content[..8] != SIGNATURE || &content[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(content[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(content[20..24].try_into().ok()?);
+    Some((width, height))
+}
+
+fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn truncate(text: &str, max: usize, middle: bool) -> String {
+    let keep = max.saturating_sub(1);
+    if middle {
+        let tail = keep / 2;
+        let head = keep - tail;
+        let chars: Vec<char> = text.chars().collect();
+        let mut terse: String = chars[..head].iter().collect();
+        terse.push('…');
+        terse.extend(&chars[chars.len() - tail..]);
+        terse
+    } else {
+        let mut terse: String = text.chars().take(keep).collect();
+        terse.push('…');
+        terse
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let content = b"\n  first line  \nsecond line";
+
+        let options = TerseOptions {
+            max_chars: None,
+            first_line_only: false,
+            middle_ellipsis: false,
+        };
+        assert_eq!(options.generate(content), "\n  first line  \nsecond line");
+
+        let options = TerseOptions {
+            first_line_only: true,
+            ..options
+        };
+        assert_eq!(options.generate(content), "first line");
+
+        let options = TerseOptions {
+            max_chars: Some(6),
+            ..options
+        };
+        assert_eq!(options.generate(content), "first…");
+
+        let options = TerseOptions {
+            middle_ellipsis: true,
+            ..options
+        };
+        assert_eq!(options.generate(content), "fir…ne");
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        assert_eq!(
+            pretty_print(r#"{"name":"s2","tags":["a"]}"#),
+            Some((
+                StructuredFormat::Json,
+                "{\n  \"name\": \"s2\",\n  \"tags\": [\n    \"a\"\n  ]\n}".into()
+            ))
+        );
+        assert_eq!(
+            pretty_print("[package]\nname = \"s2\"").map(|(format, _)| format),
+            Some(StructuredFormat::Toml)
+        );
+        assert_eq!(
+            pretty_print("name: s2\ntags: [a, b]"),
+            Some((StructuredFormat::Yaml, "name: s2\ntags:\n- a\n- b\n".into()))
+        );
+        for text in [
+            "Meeting notes: ship it",
+            "just\ntwo lines of prose",
+            "[1, 2",
+        ] {
+            assert_eq!(pretty_print(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_png_summary() {
+        let mut png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        png.extend(800u32.to_be_bytes());
+        png.extend(600u32.to_be_bytes());
+        png.resize(1_258_291, 0);
+        assert_eq!(png_summary(&png), "PNG · 800×600 · 1.2 MB");
+
+        assert_eq!(png_summary(b"not a png"), "PNG · 9 B");
+    }
+}
@@ -0,0 +1,453 @@
+//! Property-testing support, behind the `testing` feature: proptest
+//! strategies for packets and a reference model of `View::merge`, so
+//! applications can check their own packet pipelines against the crate's
+//! semantics.
+
+use std::collections::HashMap;
+
+use proptest::prelude::*;
+use proptest::sample::Index;
+use scru128::Scru128Id;
+use ssri::Integrity;
+
+use crate::source::Source;
+use crate::store::{
+    AddPacket, DeletePacket, ForkPacket, MimeType, Packet, SnapshotPacket, UpdatePacket, Version,
+};
+use crate::view::View;
+
+pub fn any_id() -> impl Strategy<Value = Scru128Id> {
+    any::<u128>().prop_map(Scru128Id::from_u128)
+}
+
+pub fn any_hash() -> impl Strategy<Value = Integrity> {
+    any::<Vec<u8>>().prop_map(|content| Integrity::from(&content))
+}
+
+impl Arbitrary for MimeType {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<MimeType>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(MimeType::TextPlain),
+            Just(MimeType::ImagePng),
+            "[a-z]{1,8}/[a-z]{1,8}".prop_map(|mime_type| MimeType::from(mime_type.as_str())),
+        ]
+        .boxed()
+    }
+}
+
+/// Independent packets with random ids. Use `history` for sequences where
+/// packets refer to each other.
+impl Arbitrary for Packet {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Packet>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let device_id = proptest::option::of("[a-z]{1,8}");
+        let source = proptest::option::of(
+            (
+                "[a-z]{1,8}",
+                proptest::option::of("[ -~]{0,16}"),
+                proptest::option::of("[a-z]{1,8}"),
+                proptest::option::of("https://[a-z]{1,8}"),
+                proptest::option::of("(/[a-z]{1,8}){1,3}"),
+            )
+                .prop_map(|(app, window_title, device, url, path)| Source {
+                    app,
+                    window_title,
+                    device,
+                    url,
+                    path,
+                }),
+        );
+        let clock = proptest::option::of(any::<u64>());
+        prop_oneof![
+            (
+                any_id(),
+                any_hash(),
+                proptest::option::of(any_id()),
+                source.clone(),
+                device_id.clone(),
+                clock.clone(),
+                proptest::collection::vec(any_hash(), 0..3),
+            )
+                .prop_map(
+                    |(id, hash, stack_id, source, device_id, clock, renditions)| {
+                        Packet::Add(AddPacket {
+                            id,
+                            hash,
+                            stack_id,
+                            source,
+                            device_id,
+                            clock,
+                            renditions,
+                        })
+                    }
+                ),
+            (
+                any_id(),
+                any_id(),
+                proptest::option::of(any_hash()),
+                proptest::option::of(any_id()),
+                source.clone(),
+                device_id.clone(),
+                clock.clone(),
+                proptest::option::of((any::<u64>(), any_id())),
+            )
+                .prop_map(
+                    |(id, source_id, hash, stack_id, source, device_id, clock, base)| {
+                        Packet::Update(UpdatePacket {
+                            id,
+                            source_id,
+                            hash,
+                            stack_id,
+                            source,
+                            device_id,
+                            clock,
+                            base: base.map(|(clock, id)| Version { clock, id }),
+                        })
+                    }
+                ),
+            (
+                any_id(),
+                any_id(),
+                proptest::option::of(any_hash()),
+                proptest::option::of(any_id()),
+                source.clone(),
+                device_id.clone(),
+                clock.clone(),
+            )
+                .prop_map(
+                    |(id, source_id, hash, stack_id, source, device_id, clock)| {
+                        Packet::Fork(ForkPacket {
+                            id,
+                            source_id,
+                            hash,
+                            stack_id,
+                            source,
+                            device_id,
+                            clock,
+                        })
+                    }
+                ),
+            (any_id(), any_id(), device_id.clone(), clock).prop_map(
+                |(id, source_id, device_id, clock)| {
+                    Packet::Delete(DeletePacket {
+                        id,
+                        source_id,
+                        device_id,
+                        clock,
+                    })
+                }
+            ),
+            (
+                any_id(),
+                any_hash(),
+                proptest::option::of(any_id()),
+                source,
+                device_id,
+                proptest::collection::vec(any_id(), 1..4),
+                any::<u64>(),
+                proptest::collection::vec(any_hash(), 0..3),
+            )
+                .prop_map(
+                    |(id, hash, stack_id, source, device_id, touched, clock, renditions)| {
+                        let version = Version {
+                            clock,
+                            id: *touched.last().unwrap(),
+                        };
+                        Packet::Snapshot(SnapshotPacket {
+                            id,
+                            hash,
+                            stack_id,
+                            source,
+                            device_id,
+                            touched,
+                            version,
+                            renditions,
+                        })
+                    }
+                ),
+        ]
+        .boxed()
+    }
+}
+
+/// Coherent packet logs of up to `max_len` packets, in id order, whose
+/// Updates, Forks, Deletes and stack ids refer to earlier items.
+pub fn history(max_len: usize) -> impl Strategy<Value = Vec<Packet>> {
+    let op = (
+        0u8..4,
+        any::<Index>(),
+        proptest::option::of(any::<Index>()),
+        proptest::option::of(0u8..8),
+    );
+    proptest::collection::vec(op, 1..max_len.max(2)).prop_map(|ops| {
+        let mut ids: Vec<Scru128Id> = Vec::new();
+        let mut packets = Vec::new();
+        for (n, (kind, source, stack, content)) in ops.into_iter().enumerate() {
+            let id = Scru128Id::from_fields(1_000 + n as u64, 0, 0, 0);
+            let hash = content.map(|c| Integrity::from([c]));
+            let stack_id = stack.filter(|_| !ids.is_empty()).map(|i| *i.get(&ids));
+            let source_id = (!ids.is_empty()).then(|| *source.get(&ids));
+
+            let packet = match (kind, source_id) {
+                (1, Some(source_id)) => Packet::Update(UpdatePacket {
+                    id,
+                    source_id,
+                    hash,
+                    // moving an item into itself isn't meaningful
+                    stack_id: stack_id.filter(|&stack_id| stack_id != source_id),
+                    source: None,
+                    device_id: None,
+                    clock: None,
+                    base: None,
+                }),
+                (2, Some(source_id)) => Packet::Fork(ForkPacket {
+                    id,
+                    source_id,
+                    hash,
+                    stack_id,
+                    source: None,
+                    device_id: None,
+                    clock: None,
+                }),
+                (3, Some(source_id)) => Packet::Delete(DeletePacket {
+                    id,
+                    source_id,
+                    device_id: None,
+                    clock: None,
+                }),
+                _ => Packet::Add(AddPacket {
+                    id,
+                    hash: hash.unwrap_or_else(|| Integrity::from([0u8])),
+                    stack_id,
+                    source: None,
+                    device_id: None,
+                    clock: None,
+                    renditions: Vec::new(),
+                }),
+            };
+            if matches!(packet, Packet::Add(_) | Packet::Fork(_)) {
+                ids.push(id);
+            }
+            packets.push(packet);
+        }
+        packets
+    })
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ModelItem {
+    pub hash: Integrity,
+    pub stack_id: Option<Scru128Id>,
+    pub touched: Vec<Scru128Id>,
+    pub last_touched: Scru128Id,
+    pub children: Vec<Scru128Id>,
+    pub forked_children: Vec<Scru128Id>,
+}
+
+/// A deliberately naive restatement of `View::merge`, for checking views
+/// built from packets in id order:
+///
+/// - Add and Snapshot create an item, appended to its stack's children.
+/// - Update changes an item's content and, when given a stack id, moves it.
+/// - Fork copies an item under a new id. The copy starts with no children of
+///   its own and inherits the original's children as forked children; it
+///   replaces the original in its stack's forked children.
+/// - Delete removes an item and takes it out of its stack's children.
+/// - Updates, Forks and Deletes of unknown items are ignored.
+/// - Every packet touches its item, and the stack the item ends up in.
+#[derive(Debug, Default)]
+pub struct Model {
+    pub items: HashMap<Scru128Id, ModelItem>,
+}
+
+impl Model {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn merge(&mut self, packet: &Packet) {
+        let id = packet.id();
+        match packet {
+            Packet::Add(AddPacket { hash, stack_id, .. }) => {
+                self.create(id, hash.clone(), *stack_id, vec![id]);
+            }
+            Packet::Snapshot(SnapshotPacket {
+                hash,
+                stack_id,
+                touched,
+                ..
+            }) => {
+                self.create(id, hash.clone(), *stack_id, touched.clone());
+            }
+            Packet::Update(UpdatePacket {
+                source_id,
+                hash,
+                stack_id,
+                ..
+            }) => {
+                let Some(item) = self.items.get(source_id).cloned() else {
+                    return;
+                };
+                let mut item = item;
+                if let Some(hash) = hash {
+                    item.hash = hash.clone();
+                }
+                if let Some(new_stack_id) = stack_id {
+                    if let Some(old_stack) = item.stack_id.and_then(|s| self.items.get_mut(&s)) {
+                        old_stack.children.retain(|child| child != source_id);
+                    }
+                    if let Some(new_stack) = self.items.get_mut(new_stack_id) {
+                        new_stack.children.push(*source_id);
+                    }
+                    item.stack_id = Some(*new_stack_id);
+                }
+                item.touched.push(id);
+                item.last_touched = item.last_touched.max(id);
+                self.touch_stack(item.stack_id, id);
+                self.items.insert(*source_id, item);
+            }
+            Packet::Fork(ForkPacket {
+                source_id,
+                hash,
+                stack_id,
+                ..
+            }) => {
+                let Some(source) = self.items.get(source_id) else {
+                    return;
+                };
+                let mut item = source.clone();
+                item.forked_children = source.children.clone();
+                item.children = Vec::new();
+                if let Some(hash) = hash {
+                    item.hash = hash.clone();
+                }
+                if stack_id.is_some() {
+                    item.stack_id = *stack_id;
+                }
+                item.touched.push(id);
+                item.last_touched = id;
+                if let Some(stack) = item.stack_id.and_then(|s| self.items.get_mut(&s)) {
+                    stack.forked_children.retain(|child| child != source_id);
+                    stack.children.push(id);
+                }
+                self.touch_stack(item.stack_id, id);
+                self.items.insert(id, item);
+            }
+            Packet::Delete(DeletePacket { source_id, .. }) => {
+                if let Some(item) = self.items.remove(source_id) {
+                    if let Some(stack) = item.stack_id.and_then(|s| self.items.get_mut(&s)) {
+                        stack.children.retain(|child| child != source_id);
+                    }
+                    self.touch_stack(item.stack_id, id);
+                }
+            }
+            // accesses and policies don't change the tree
+            Packet::Access(_) | Packet::Policy(_) => {}
+        }
+    }
+
+    fn create(
+        &mut self,
+        id: Scru128Id,
+        hash: Integrity,
+        stack_id: Option<Scru128Id>,
+        touched: Vec<Scru128Id>,
+    ) {
+        let last_touched = touched.iter().copied().max().unwrap_or(id);
+        if let Some(stack) = stack_id.and_then(|s| self.items.get_mut(&s)) {
+            stack.children.push(id);
+        }
+        self.touch_stack(stack_id, last_touched);
+        self.items.insert(
+            id,
+            ModelItem {
+                hash,
+                stack_id,
+                touched,
+                last_touched,
+                children: Vec::new(),
+                forked_children: Vec::new(),
+            },
+        );
+    }
+
+    fn touch_stack(&mut self, stack_id: Option<Scru128Id>, id: Scru128Id) {
+        if let Some(stack) = stack_id.and_then(|s| self.items.get_mut(&s)) {
+            stack.last_touched = stack.last_touched.max(id);
+        }
+    }
+
+    /// Checks `view` agrees with the model. Children are compared as sets,
+    /// but must be kept in creation order.
+    pub fn check(&self, view: &View) -> Result<(), String> {
+        if self.items.len() != view.items.len() {
+            return Err(format!(
+                "expected {} items, view has {}",
+                self.items.len(),
+                view.items.len()
+            ));
+        }
+        for (id, expected) in &self.items {
+            let Some(item) = view.items.get(id) else {
+                return Err(format!("item {} is missing from the view", id));
+            };
+            if !item.children.windows(2).all(|pair| pair[0] < pair[1]) {
+                return Err(format!("children of {} aren't in creation order", id));
+            }
+            let sorted = |ids: &[Scru128Id]| {
+                let mut ids = ids.to_vec();
+                ids.sort();
+                ids
+            };
+            let actual = ModelItem {
+                hash: item.hash.clone(),
+                stack_id: item.stack_id,
+                touched: item.touched.clone(),
+                last_touched: item.last_touched,
+                children: sorted(&item.children),
+                forked_children: sorted(&item.forked_children),
+            };
+            let expected = ModelItem {
+                children: sorted(&expected.children),
+                forked_children: sorted(&expected.forked_children),
+                ..expected.clone()
+            };
+            if actual != expected {
+                return Err(format!(
+                    "item {} differs: expected {:?}, view has {:?}",
+                    id, expected, actual
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_merge_matches_model(history in history(40)) {
+            let mut view = View::new();
+            let mut model = Model::new();
+            for packet in history {
+                model.merge(&packet);
+                view.merge(packet);
+            }
+            model.check(&view).map_err(TestCaseError::fail)?;
+        }
+
+        #[test]
+        fn test_packet_roundtrip(packet in any::<Packet>()) {
+            let encoded = bincode::serialize(&packet).unwrap();
+            prop_assert_eq!(bincode::deserialize::<Packet>(&encoded).unwrap(), packet);
+        }
+    }
+}
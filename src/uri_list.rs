@@ -0,0 +1,91 @@
+//! `text/uri-list` content, the clipboard flavor file managers use when files
+//! are copied.
+
+use std::path::PathBuf;
+
+pub const MIME_TYPE: &str = "text/uri-list";
+
+/// A URI from a uri-list, and the local file it names, if it's a `file:` URI.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FileReference {
+    pub uri: String,
+    pub path: Option<PathBuf>,
+}
+
+impl FileReference {
+    /// Whether the file is still there. Always false for URIs that aren't
+    /// local files.
+    pub fn exists(&self) -> bool {
+        self.path.as_ref().is_some_and(|path| path.exists())
+    }
+}
+
+/// The URIs in a uri-list, skipping comments and blank lines.
+pub fn parse(text: &str) -> Vec<FileReference> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|uri| FileReference {
+            uri: uri.to_string(),
+            path: file_path(uri),
+        })
+        .collect()
+}
+
+/// The path a `file:` URI names, percent-decoded. A host, other than
+/// `localhost`, isn't local and gives `None`.
+fn file_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = match rest.find('/')? {
+        0 => rest,
+        i if &rest[..i] == "localhost" => &rest[i..],
+        _ => return None,
+    };
+    Some(PathBuf::from(percent_decode(path)?))
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let references = parse(
+            "# copied from Finder\r\n\
+             file:///Users/jo/My%20Notes.txt\r\n\
+             file://localhost/tmp/a.png\r\n\
+             file://server/share/b.txt\r\n\
+             \r\n\
+             https://example.com/\r\n",
+        );
+        let paths: Vec<Option<PathBuf>> = references.iter().map(|r| r.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                Some(PathBuf::from("/Users/jo/My Notes.txt")),
+                Some(PathBuf::from("/tmp/a.png")),
+                None,
+                None,
+            ]
+        );
+        assert_eq!(references[3].uri, "https://example.com/");
+        assert!(!references[3].exists());
+    }
+}
@@ -1,13 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use scru128::Scru128Id;
 use ssri::Integrity;
 
-use crate::store::Packet;
+use crate::kind::ContentKind;
+use crate::link_preview::LinkPreview;
+use crate::store::{
+    AccessKind, Content, ForkPacket, Packet, PacketKind, Store, UpdatePacket, Version,
+};
+use crate::uri_list::FileReference;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct Item {
     pub id: Scru128Id,
     pub last_touched: Scru128Id,
@@ -16,12 +21,279 @@ pub struct Item {
     pub stack_id: Option<Scru128Id>,
     pub children: Vec<Scru128Id>,
     pub forked_children: Vec<Scru128Id>,
+    /// The device the item was created on.
+    pub device_id: Option<String>,
+    /// The version of the packet that last changed the item's content or
+    /// stack.
+    pub version: Version,
+    /// The newest version of any packet merged for the item, applied or not.
+    /// Updates based on an older version conflict with it.
+    pub seen: Version,
+    /// Other renditions of the content, see `AddPacket::renditions`.
+    pub renditions: Vec<Integrity>,
+    /// The log2 of the sum, over each touch and access, of 2 to the power of the
+    /// touch's time in half-lives. Ordering by it orders by `frecency` at
+    /// any moment, without rescoring items as time passes.
+    pub(crate) frecency_key: f64,
+    pub accesses: Accesses,
+}
+
+/// How often an item was used, see `Store::record_access`.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Accesses {
+    pub pasted: u64,
+    pub previewed: u64,
+    pub executed: u64,
+    /// The id of the newest access merged, which dates it.
+    pub last_accessed: Option<Scru128Id>,
+}
+
+impl Accesses {
+    pub fn count(&self, kind: AccessKind) -> u64 {
+        match kind {
+            AccessKind::Pasted => self.pasted,
+            AccessKind::Previewed => self.previewed,
+            AccessKind::Executed => self.executed,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.pasted + self.previewed + self.executed
+    }
+
+    fn record(&mut self, kind: AccessKind, id: Scru128Id) {
+        match kind {
+            AccessKind::Pasted => self.pasted += 1,
+            AccessKind::Previewed => self.previewed += 1,
+            AccessKind::Executed => self.executed += 1,
+        }
+        self.last_accessed = self.last_accessed.max(Some(id));
+    }
+}
+
+/// How long it takes a touch to count half as much towards an item's
+/// frecency: a week.
+pub const FRECENCY_HALF_LIFE_MILLIS: u64 = 7 * 24 * 60 * 60 * 1000;
+
+/// `frecency_key` with another touch at `touched` counted.
+fn touch_frecency(frecency_key: f64, touched: Scru128Id) -> f64 {
+    let weight = touched.timestamp() as f64 / FRECENCY_HALF_LIFE_MILLIS as f64;
+    let (high, low) = match frecency_key > weight {
+        true => (frecency_key, weight),
+        false => (weight, frecency_key),
+    };
+    // log2(2^high + 2^low), without overflowing
+    high + (low - high).exp2().ln_1p() / std::f64::consts::LN_2
+}
+
+impl Item {
+    /// How often and recently the item has been touched or used, as of
+    /// `now_millis`: each touch or access counts 1 when it happens, decaying by half each
+    /// `FRECENCY_HALF_LIFE_MILLIS`.
+    pub fn frecency(&self, now_millis: u64) -> f64 {
+        (self.frecency_key - now_millis as f64 / FRECENCY_HALF_LIFE_MILLIS as f64).exp2()
+    }
+
+    /// The URIs the item lists if its content is a `text/uri-list`, with the
+    /// local files they name.
+    pub fn file_references(&self, store: &Store) -> Vec<FileReference> {
+        if !store
+            .content_meta(&self.hash)
+            .is_some_and(|meta| meta.is_uri_list())
+        {
+            return Vec::new();
+        }
+        match store.cas_read(&self.hash) {
+            Ok(Some(content)) => crate::uri_list::parse(&String::from_utf8_lossy(&content)),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The preview of the page the item's URL points to, see
+    /// `Store::enrich_link_previews`.
+    pub fn link_preview(&self, store: &Store) -> Option<LinkPreview> {
+        store.link_preview(&self.hash)
+    }
+
+    /// What the item's content looks like, e.g. a URL or a color.
+    pub fn kind(&self, store: &Store) -> Option<ContentKind> {
+        store.content_meta(&self.hash)?.kind
+    }
+}
+
+/// What `View::select_within_budget_measured` counts against the budget.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum BudgetMeasure {
+    Tiktokens,
+    Lines,
+    Words,
+    Chars,
+}
+
+impl BudgetMeasure {
+    pub fn of(&self, meta: &Content) -> usize {
+        match self {
+            BudgetMeasure::Tiktokens => meta.tiktokens,
+            BudgetMeasure::Lines => meta.counts.lines,
+            BudgetMeasure::Words => meta.counts.words,
+            BudgetMeasure::Chars => meta.counts.chars,
+        }
+    }
+}
+
+/// Which end of a stack `View::select_within_budget` starts filling from.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum BudgetOrder {
+    MostRecent,
+    LeastRecent,
+}
+
+/// How a view orders its roots, see `View::root_order`, or a stack's
+/// children, see `View::children`.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum SortSpec {
+    /// By creation, i.e. by id. Moving or updating an item doesn't change its
+    /// position.
+    #[default]
+    Created,
+    /// Least recently touched first.
+    LastTouched,
+    /// Least frecent first, so the items touched most, and most recently,
+    /// come last, see `Item::frecency`.
+    Frecency,
+}
+
+pub type ChildOrder = SortSpec;
+
+impl SortSpec {
+    fn compare(&self, a: &Item, b: &Item) -> std::cmp::Ordering {
+        match self {
+            SortSpec::Created => a.id.cmp(&b.id),
+            SortSpec::LastTouched => (a.last_touched, a.id).cmp(&(b.last_touched, b.id)),
+            SortSpec::Frecency => a
+                .frecency_key
+                .total_cmp(&b.frecency_key)
+                .then(a.id.cmp(&b.id)),
+        }
+    }
+
+    /// Where `item` sorts, before its id breaks ties: comparing keys
+    /// agrees with `compare`.
+    fn key(&self, item: &Item) -> u128 {
+        match self {
+            SortSpec::Created => 0,
+            SortSpec::LastTouched => item.last_touched.to_u128(),
+            SortSpec::Frecency => {
+                // `f64::total_cmp`'s order, as an unsigned integer's
+                let bits = item.frecency_key.to_bits() as i64;
+                let bits = bits ^ (((bits >> 63) as u64) >> 1) as i64;
+                (bits as u64 ^ (1 << 63)) as u128
+            }
+        }
+    }
+}
+
+/// How `View::merge` resolves an update made concurrently with one already
+/// merged, i.e. one whose `base` predates the item's `seen` version. Packets
+/// merge in log order, and a `Packet::Policy` sets the policy for those
+/// merged after it, so replicas with the same log converge.
+#[derive(PartialEq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// The update with the highest version wins.
+    #[default]
+    LastWriterWins,
+    /// The update merged first wins; conflicting updates are dropped.
+    FirstWins,
+    /// A conflicting update is applied to a fork of the item, leaving the
+    /// item as it was.
+    KeepBothAsFork,
 }
 
 pub struct View {
     pub items: HashMap<Scru128Id, Item>,
+    pub child_order: ChildOrder,
+    /// How `root_ids` are ordered; by default `SortSpec::LastTouched`.
+    root_order: SortSpec,
+    pub conflict_policy: ConflictPolicy,
+    /// Items without a stack, by their `root_order` key and then id.
+    /// Maintained by `merge`.
+    roots: BTreeSet<(u128, Scru128Id)>,
+    /// Each root's key in `roots`, as it was when the root was placed.
+    root_keys: HashMap<Scru128Id, u128>,
+    /// Ids of deleted items, so `try_merge` can tell a packet racing a
+    /// delete from one about an item never seen.
+    deleted: HashSet<Scru128Id>,
+}
+
+/// A view's size, see `View::stats`.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct ViewStats {
+    pub items: usize,
+    /// Entries in every item's `touched`, which grows with each packet
+    /// merged for the item, snapshots included.
+    pub touched: usize,
+    /// The most entries in any one item's `touched`.
+    pub max_touched: usize,
+    /// Stack to child edges, counting forked children.
+    pub children: usize,
+    /// Deleted ids kept for `try_merge`.
+    pub deleted: usize,
+    /// An estimate of the memory the view holds, in bytes, counting
+    /// allocated capacity rather than what's in use.
+    pub bytes: usize,
+}
+
+/// What merging a packet did, see `View::try_merge`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum ViewEvent {
+    Added(Scru128Id),
+    Forked {
+        id: Scru128Id,
+        source_id: Scru128Id,
+    },
+    /// The item's content changed.
+    Updated(Scru128Id),
+    Moved {
+        id: Scru128Id,
+        from: Option<Scru128Id>,
+        to: Option<Scru128Id>,
+    },
+    /// The item was touched without changing, e.g. by an update that lost
+    /// a conflict.
+    Touched(Scru128Id),
+    Accessed(Scru128Id),
+    Deleted(Scru128Id),
+}
+
+/// Why `View::try_merge` refused a packet. The view is left as it was.
+#[derive(PartialEq, Debug, Clone)]
+pub enum MergeError {
+    /// The packet refers to an item the view deleted: it was written
+    /// concurrently with the delete, and is harmless to drop.
+    SourceDeleted { id: Scru128Id, source_id: Scru128Id },
+    /// The packet refers to an item the view has never had: it arrived
+    /// before the packet adding the item, or that packet was lost.
+    UnknownSource { id: Scru128Id, source_id: Scru128Id },
+    /// The packet would add an item the view already has.
+    DuplicateId(Scru128Id),
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::SourceDeleted { id, source_id } => {
+                write!(f, "packet {} refers to deleted item {}", id, source_id)
+            }
+            MergeError::UnknownSource { id, source_id } => {
+                write!(f, "packet {} refers to unknown item {}", id, source_id)
+            }
+            MergeError::DuplicateId(id) => write!(f, "item {} already exists", id),
+        }
+    }
 }
 
+impl std::error::Error for MergeError {}
+
 impl Default for View {
     fn default() -> Self {
         Self::new()
@@ -32,10 +304,156 @@ impl View {
     pub fn new() -> Self {
         View {
             items: HashMap::new(),
+            child_order: ChildOrder::default(),
+            root_order: SortSpec::LastTouched,
+            conflict_policy: ConflictPolicy::default(),
+            roots: BTreeSet::new(),
+            root_keys: HashMap::new(),
+            deleted: HashSet::new(),
+        }
+    }
+
+    pub fn with_child_order(child_order: ChildOrder) -> Self {
+        View {
+            child_order,
+            ..View::new()
+        }
+    }
+
+    /// A view ordering its roots by `root_order`. Only merged packets are
+    /// ordered, so set it before merging.
+    pub fn with_root_order(root_order: SortSpec) -> Self {
+        View {
+            root_order,
+            ..View::new()
+        }
+    }
+
+    pub fn root_order(&self) -> SortSpec {
+        self.root_order
+    }
+
+    pub fn with_conflict_policy(conflict_policy: ConflictPolicy) -> Self {
+        View {
+            conflict_policy,
+            ..View::new()
         }
     }
 
     pub fn merge(&mut self, packet: Packet) {
+        let id = match &packet {
+            Packet::Add(_) | Packet::Fork(_) | Packet::Snapshot(_) => packet.id(),
+            Packet::Update(packet) => packet.source_id,
+            Packet::Delete(packet) => packet.source_id,
+            Packet::Access(packet) => packet.source_id,
+            // it changes no item
+            Packet::Policy(_) => return self.apply(packet),
+        };
+        let packet_id = packet.id();
+        let old_stack_id = self.items.get(&id).and_then(|item| item.stack_id);
+
+        self.apply(packet);
+
+        let new_stack_id = self.items.get(&id).and_then(|item| item.stack_id);
+        // an update kept as a fork may have added an item of its own
+        let fork_id = Some(packet_id).filter(|&fork_id| fork_id != id);
+        for id in [Some(id), fork_id, old_stack_id, new_stack_id]
+            .into_iter()
+            .flatten()
+        {
+            self.sync_root(id);
+        }
+    }
+
+    /// Like `merge`, but refuses packets that don't fit the view, rather
+    /// than ignoring them, and reports what merging did, so sync pipelines
+    /// can tell dropped operations from applied ones.
+    pub fn try_merge(&mut self, packet: Packet) -> Result<Vec<ViewEvent>, MergeError> {
+        let id = packet.id();
+        let source_id = match &packet {
+            Packet::Add(_) | Packet::Snapshot(_) | Packet::Policy(_) => None,
+            Packet::Fork(packet) => Some(packet.source_id),
+            Packet::Update(packet) => Some(packet.source_id),
+            Packet::Delete(packet) => Some(packet.source_id),
+            Packet::Access(packet) => Some(packet.source_id),
+        };
+        let adds = matches!(
+            packet,
+            Packet::Add(_) | Packet::Snapshot(_) | Packet::Fork(_)
+        );
+        if adds && self.items.contains_key(&id) {
+            return Err(MergeError::DuplicateId(id));
+        }
+        if let Some(source_id) = source_id.filter(|id| !self.items.contains_key(id)) {
+            return Err(match self.deleted.contains(&source_id) {
+                true => MergeError::SourceDeleted { id, source_id },
+                false => MergeError::UnknownSource { id, source_id },
+            });
+        }
+
+        let target = source_id.unwrap_or(id);
+        let before = self.items.get(&target).cloned();
+        let kind = packet.kind();
+        self.merge(packet);
+        let after = self.items.get(&target);
+
+        let mut events = Vec::new();
+        match (kind, before, after) {
+            (PacketKind::Add | PacketKind::Snapshot, _, _) => events.push(ViewEvent::Added(id)),
+            (PacketKind::Fork, _, _) => events.push(ViewEvent::Forked {
+                id,
+                source_id: target,
+            }),
+            (PacketKind::Delete, _, _) => events.push(ViewEvent::Deleted(target)),
+            (PacketKind::Access, _, _) => events.push(ViewEvent::Accessed(target)),
+            // a conflict kept as a fork
+            (PacketKind::Update, _, _) if self.items.contains_key(&id) => {
+                events.push(ViewEvent::Forked {
+                    id,
+                    source_id: target,
+                });
+            }
+            (PacketKind::Update, Some(before), Some(after)) => {
+                if after.hash != before.hash {
+                    events.push(ViewEvent::Updated(target));
+                }
+                if after.stack_id != before.stack_id {
+                    events.push(ViewEvent::Moved {
+                        id: target,
+                        from: before.stack_id,
+                        to: after.stack_id,
+                    });
+                }
+                if events.is_empty() {
+                    events.push(ViewEvent::Touched(target));
+                }
+            }
+            (PacketKind::Update | PacketKind::Policy, _, _) => {}
+        }
+        Ok(events)
+    }
+
+    fn sync_root(&mut self, id: Scru128Id) {
+        if let Some(key) = self.root_keys.remove(&id) {
+            self.roots.remove(&(key, id));
+        }
+        if self
+            .items
+            .get(&id)
+            .is_some_and(|item| item.stack_id.is_none())
+        {
+            self.insert_root(id);
+        }
+    }
+
+    fn insert_root(&mut self, id: Scru128Id) {
+        let key = self.root_order.key(&self.items[&id]);
+        self.roots.insert((key, id));
+        self.root_keys.insert(id, key);
+    }
+
+    fn apply(&mut self, packet: Packet) {
+        let version = packet.version();
         match packet {
             Packet::Add(packet) => {
                 let item = Item {
@@ -46,11 +464,47 @@ impl View {
                     stack_id: packet.stack_id,
                     children: Vec::new(),
                     forked_children: Vec::new(),
+                    device_id: packet.device_id,
+                    version,
+                    seen: version,
+                    renditions: packet.renditions,
+                    frecency_key: touch_frecency(f64::NEG_INFINITY, packet.id),
+                    accesses: Accesses::default(),
+                };
+
+                if let Some(stack) = packet.stack_id.and_then(|id| self.items.get_mut(&id)) {
+                    insert_child(&mut stack.children, packet.id);
+                    stack.last_touched = stack.last_touched.max(packet.id);
+                }
+                self.items.insert(packet.id, item);
+            }
+
+            Packet::Snapshot(packet) => {
+                let frecency_key = packet
+                    .touched
+                    .iter()
+                    .fold(f64::NEG_INFINITY, |key, &touched| {
+                        touch_frecency(key, touched)
+                    });
+                let item = Item {
+                    id: packet.id,
+                    last_touched: packet.touched.last().copied().unwrap_or(packet.id),
+                    touched: packet.touched,
+                    hash: packet.hash,
+                    stack_id: packet.stack_id,
+                    children: Vec::new(),
+                    forked_children: Vec::new(),
+                    device_id: packet.device_id,
+                    version,
+                    seen: version,
+                    renditions: packet.renditions,
+                    frecency_key,
+                    accesses: Accesses::default(),
                 };
 
                 if let Some(stack) = packet.stack_id.and_then(|id| self.items.get_mut(&id)) {
-                    stack.children.push(packet.id);
-                    stack.last_touched = packet.id;
+                    insert_child(&mut stack.children, packet.id);
+                    stack.last_touched = stack.last_touched.max(item.last_touched);
                 }
                 self.items.insert(packet.id, item);
             }
@@ -59,26 +513,44 @@ impl View {
                 if let Some(item) = self.items.get(&packet.source_id).cloned() {
                     let mut item = item;
 
-                    if let Some(hash) = packet.hash {
-                        item.hash = hash;
+                    let changes = packet.hash.is_some() || packet.stack_id.is_some();
+                    let conflict = changes && packet.base.is_some_and(|base| base < item.seen);
+                    item.seen = item.seen.max(version);
+                    if conflict && self.conflict_policy == ConflictPolicy::KeepBothAsFork {
+                        self.items.insert(packet.source_id, item);
+                        self.fork_conflict(packet);
+                        return;
                     }
 
-                    if let Some(new_stack_id) = packet.stack_id {
-                        if let Some(old_stack) =
-                            item.stack_id.and_then(|id| self.items.get_mut(&id))
-                        {
-                            old_stack.children.retain(|&id| id != packet.source_id);
+                    // Only changes newer than the item's current state apply,
+                    // so replicas converge whatever order they receive
+                    // concurrent updates in.
+                    let first_wins = conflict && self.conflict_policy == ConflictPolicy::FirstWins;
+                    if changes && !first_wins && version > item.version {
+                        item.version = version;
+                        if let Some(hash) = packet.hash {
+                            item.hash = hash;
+                            item.renditions.clear();
                         }
-                        item.stack_id = Some(new_stack_id);
-                        if let Some(new_stack) = self.items.get_mut(&new_stack_id) {
-                            new_stack.children.push(packet.source_id);
+
+                        if let Some(new_stack_id) = packet.stack_id {
+                            if let Some(old_stack) =
+                                item.stack_id.and_then(|id| self.items.get_mut(&id))
+                            {
+                                old_stack.children.retain(|&id| id != packet.source_id);
+                            }
+                            item.stack_id = Some(new_stack_id);
+                            if let Some(new_stack) = self.items.get_mut(&new_stack_id) {
+                                insert_child(&mut new_stack.children, packet.source_id);
+                            }
                         }
                     }
 
                     item.touched.push(packet.id);
-                    item.last_touched = packet.id;
+                    item.last_touched = item.last_touched.max(packet.id);
+                    item.frecency_key = touch_frecency(item.frecency_key, packet.id);
                     if let Some(stack) = item.stack_id.and_then(|id| self.items.get_mut(&id)) {
-                        stack.last_touched = packet.id;
+                        stack.last_touched = stack.last_touched.max(packet.id);
                     }
 
                     self.items.insert(packet.source_id, item);
@@ -95,57 +567,331 @@ impl View {
 
                     if let Some(hash) = packet.hash {
                         new_item.hash = hash;
+                        new_item.renditions.clear();
                     }
 
                     if let Some(new_stack_id) = packet.stack_id {
                         new_item.stack_id = Some(new_stack_id);
                     }
 
+                    new_item.device_id = packet.device_id;
+                    new_item.version = version;
+                    new_item.seen = version;
                     new_item.touched.push(packet.id);
                     new_item.last_touched = packet.id;
+                    new_item.frecency_key = touch_frecency(new_item.frecency_key, packet.id);
 
                     if let Some(stack) = new_item.stack_id.and_then(|id| self.items.get_mut(&id)) {
                         // Remove the forked item from forked_children
                         stack.forked_children.retain(|&id| id != packet.source_id);
                         // And add the new item to children
-                        stack.children.push(packet.id);
-                        stack.last_touched = packet.id;
+                        insert_child(&mut stack.children, packet.id);
+                        stack.last_touched = stack.last_touched.max(packet.id);
                     }
 
                     self.items.insert(packet.id, new_item);
                 }
             }
+            Packet::Policy(packet) => self.conflict_policy = packet.conflict_policy,
+            // counted towards frecency like a touch, but without touching
+            // the item
+            Packet::Access(packet) => {
+                if let Some(item) = self.items.get_mut(&packet.source_id) {
+                    item.accesses.record(packet.kind, packet.id);
+                    item.frecency_key = touch_frecency(item.frecency_key, packet.id);
+                }
+            }
             Packet::Delete(packet) => {
+                self.deleted.insert(packet.source_id);
                 if let Some(item) = self.items.remove(&packet.source_id) {
                     if let Some(stack) = item.stack_id.and_then(|id| self.items.get_mut(&id)) {
                         stack.children.retain(|&id| id != packet.source_id);
-                        stack.last_touched = packet.id;
+                        stack.last_touched = stack.last_touched.max(packet.id);
                     }
                 }
             }
         }
     }
 
-    pub fn root(&self) -> Vec<Item> {
-        let mut root_items = self
+    /// Applies a conflicting update to a new item forked from its target, with
+    /// the update's id.
+    fn fork_conflict(&mut self, packet: UpdatePacket) {
+        self.apply(Packet::Fork(ForkPacket {
+            id: packet.id,
+            source_id: packet.source_id,
+            hash: packet.hash,
+            stack_id: packet.stack_id,
+            source: packet.source,
+            device_id: packet.device_id,
+            clock: packet.clock,
+        }));
+    }
+
+    pub fn get(&self, id: &Scru128Id) -> Option<&Item> {
+        self.items.get(id)
+    }
+
+    /// Counts what the view holds, e.g. to notice an item's `touched`
+    /// growing without bound.
+    pub fn stats(&self) -> ViewStats {
+        use std::mem::size_of;
+        let ids = |ids: &Vec<Scru128Id>| ids.capacity() * size_of::<Scru128Id>();
+        let hash = |hash: &Integrity| {
+            let digests = hash.hashes.iter().map(|hash| hash.digest.capacity());
+            hash.hashes.capacity() * size_of::<ssri::Hash>() + digests.sum::<usize>()
+        };
+        let mut stats = ViewStats {
+            items: self.items.len(),
+            deleted: self.deleted.len(),
+            bytes: size_of::<View>()
+                + self.items.capacity() * (size_of::<Scru128Id>() + size_of::<Item>())
+                + self.roots.len() * size_of::<(u128, Scru128Id)>()
+                + self.root_keys.capacity() * (size_of::<Scru128Id>() + size_of::<u128>())
+                + self.deleted.capacity() * size_of::<Scru128Id>(),
+            ..Default::default()
+        };
+        for item in self.items.values() {
+            stats.touched += item.touched.len();
+            stats.max_touched = stats.max_touched.max(item.touched.len());
+            stats.children += item.children.len() + item.forked_children.len();
+            stats.bytes += ids(&item.touched)
+                + ids(&item.children)
+                + ids(&item.forked_children)
+                + hash(&item.hash)
+                + item.renditions.capacity() * size_of::<Integrity>()
+                + item.renditions.iter().map(hash).sum::<usize>()
+                + item.device_id.as_ref().map_or(0, String::capacity);
+        }
+        stats
+    }
+
+    /// Items whose stack isn't in the view, e.g. because it was deleted, so
+    /// they're neither roots nor any stack's children. Oldest first. See
+    /// `Store::repair_orphans`.
+    pub fn orphans(&self) -> Vec<&Item> {
+        let mut orphans: Vec<&Item> = self
             .items
             .values()
-            .filter(|item| item.stack_id.is_none())
+            .filter(|item| {
+                item.stack_id
+                    .is_some_and(|id| !self.items.contains_key(&id))
+            })
+            .collect();
+        orphans.sort_by_key(|item| item.id);
+        orphans
+    }
+
+    /// Ids of the items without a stack, in the view's `root_order`.
+    pub fn root_ids(&self) -> Vec<Scru128Id> {
+        self.roots.iter().map(|&(_, id)| id).collect()
+    }
+
+    /// Borrowing counterpart of `root`.
+    pub fn iter_root(&self) -> impl Iterator<Item = &Item> {
+        self.roots.iter().filter_map(|(_, id)| self.items.get(id))
+    }
+
+    pub fn root(&self) -> Vec<Item> {
+        self.roots
+            .iter()
+            .filter_map(|(_, id)| self.items.get(id))
             .cloned()
-            .collect::<Vec<_>>();
-        root_items.sort_by_key(|item| item.last_touched);
-        root_items
+            .collect()
     }
 
+    /// The ids of `item`'s children and forked children, in the view's
+    /// `child_order`.
     pub fn children(&self, item: &Item) -> Vec<Scru128Id> {
+        self.children_by(item, self.child_order)
+    }
+
+    fn children_by(&self, item: &Item, order: ChildOrder) -> Vec<Scru128Id> {
         let mut children = item.children.clone();
         children.extend(&item.forked_children);
-        children.sort_by_key(|child| {
-            self.items
-                .get(child)
-                .map(|item| item.last_touched)
-                .unwrap_or_default()
-        });
+        match order {
+            ChildOrder::Created => children.sort(),
+            ChildOrder::LastTouched => children.sort_by_key(|child| {
+                self.items
+                    .get(child)
+                    .map(|item| item.last_touched)
+                    .unwrap_or_default()
+            }),
+            ChildOrder::Frecency => {
+                children.sort_by(|a, b| match (self.items.get(a), self.items.get(b)) {
+                    (Some(a), Some(b)) => order.compare(a, b),
+                    (a, b) => a.is_some().cmp(&b.is_some()),
+                })
+            }
+        }
         children
     }
+
+    /// Borrowing counterpart of `children`, skipping ids whose items are gone.
+    pub fn iter_children<'a>(&'a self, item: &Item) -> impl Iterator<Item = &'a Item> {
+        self.children(item)
+            .into_iter()
+            .filter_map(|id| self.items.get(&id))
+    }
+
+    /// Walks the item tree from the roots, yielding each item with its depth
+    /// and visiting an item's children before its later siblings.
+    pub fn iter_depth_first(&self) -> Walk<'_> {
+        Walk::new(self, true)
+    }
+
+    /// Walks the item tree from the roots one level at a time.
+    pub fn iter_breadth_first(&self) -> Walk<'_> {
+        Walk::new(self, false)
+    }
+
+    /// A view holding just `stack_id` and its descendants, with the stack as
+    /// its only root.
+    pub fn sub_view(&self, stack_id: Scru128Id) -> View {
+        let mut sub_view = View {
+            child_order: self.child_order,
+            root_order: self.root_order,
+            ..View::new()
+        };
+        if !self.items.contains_key(&stack_id) {
+            return sub_view;
+        }
+
+        let mut pending = vec![stack_id];
+        while let Some(id) = pending.pop() {
+            if sub_view.items.contains_key(&id) {
+                continue;
+            }
+            if let Some(item) = self.items.get(&id) {
+                pending.extend(&item.children);
+                pending.extend(&item.forked_children);
+                sub_view.items.insert(id, item.clone());
+            }
+        }
+        sub_view.insert_root(stack_id);
+        sub_view
+    }
+
+    /// Picks children of `stack_id`, starting from the end given by `order`,
+    /// whose summed `tiktokens` fit within `max_tiktokens`. Children that
+    /// don't fit are skipped in favour of smaller ones further along. Returns
+    /// the chosen ids in selection order and their total.
+    pub fn select_within_budget(
+        &self,
+        store: &Store,
+        stack_id: Scru128Id,
+        max_tiktokens: usize,
+        order: BudgetOrder,
+    ) -> (Vec<Scru128Id>, usize) {
+        self.select_within_budget_measured(
+            store,
+            stack_id,
+            max_tiktokens,
+            BudgetMeasure::Tiktokens,
+            order,
+        )
+    }
+
+    /// Like `select_within_budget`, with the budget in lines, words or chars.
+    pub fn select_within_budget_measured(
+        &self,
+        store: &Store,
+        stack_id: Scru128Id,
+        budget: usize,
+        measure: BudgetMeasure,
+        order: BudgetOrder,
+    ) -> (Vec<Scru128Id>, usize) {
+        self.select_within_budget_by(store, stack_id, budget, measure, order, |_| true)
+    }
+
+    pub(crate) fn select_within_budget_by(
+        &self,
+        store: &Store,
+        stack_id: Scru128Id,
+        budget: usize,
+        measure: BudgetMeasure,
+        order: BudgetOrder,
+        include: impl Fn(&Content) -> bool,
+    ) -> (Vec<Scru128Id>, usize) {
+        let Some(stack) = self.items.get(&stack_id) else {
+            return (Vec::new(), 0);
+        };
+
+        let mut children = self.children_by(stack, ChildOrder::LastTouched);
+        if order == BudgetOrder::MostRecent {
+            children.reverse();
+        }
+
+        let mut selected = Vec::new();
+        let mut total = 0;
+        for child_id in children {
+            let size = self
+                .items
+                .get(&child_id)
+                .and_then(|child| store.content_meta(&child.hash))
+                .filter(|meta| include(meta))
+                .map(|meta| measure.of(&meta));
+            if let Some(size) = size {
+                if total + size <= budget {
+                    total += size;
+                    selected.push(child_id);
+                }
+            }
+        }
+        (selected, total)
+    }
+}
+
+/// Children are kept sorted by id, so a stack lists them in creation order
+/// however they arrived.
+fn insert_child(children: &mut Vec<Scru128Id>, id: Scru128Id) {
+    if let Err(pos) = children.binary_search(&id) {
+        children.insert(pos, id);
+    }
+}
+
+/// Iterator returned by `View::iter_depth_first` and
+/// `View::iter_breadth_first`. Roots and children follow the view's
+/// ordering; items reachable more than once are yielded the first time only.
+pub struct Walk<'a> {
+    view: &'a View,
+    depth_first: bool,
+    pending: VecDeque<(usize, Scru128Id)>,
+    visited: HashSet<Scru128Id>,
+}
+
+impl<'a> Walk<'a> {
+    fn new(view: &'a View, depth_first: bool) -> Self {
+        Walk {
+            view,
+            depth_first,
+            pending: view.roots.iter().map(|&(_, id)| (0, id)).collect(),
+            visited: HashSet::new(),
+        }
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (usize, &'a Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, id)) = self.pending.pop_front() {
+            let Some(item) = self.view.items.get(&id) else {
+                continue;
+            };
+            if !self.visited.insert(id) {
+                continue;
+            }
+            let children = self.view.children(item);
+            if self.depth_first {
+                for child in children.into_iter().rev() {
+                    self.pending.push_front((depth + 1, child));
+                }
+            } else {
+                self.pending
+                    .extend(children.into_iter().map(|child| (depth + 1, child)));
+            }
+            return Some((depth, item));
+        }
+        None
+    }
 }
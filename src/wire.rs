@@ -0,0 +1,437 @@
+//! The JSON packets are handed to other programs as, e.g. by `CdcSink`.
+//! Unlike `Packet`'s own serde representation, which follows the Rust types
+//! and is what the log stores, it's documented and kept stable:
+//!
+//! ```json
+//! {
+//!   "type": "add",
+//!   "data": {
+//!     "id": "036z8hpsqo7e4l2ke5hqwuwx7",
+//!     "timestamp": "2024-05-01T09:30:00.123Z",
+//!     "hash": "sha256-...",
+//!     "stack_id": null,
+//!     "source": { "app": "Safari", "window_title": null, "device": null, "url": null, "path": null },
+//!     "device_id": null,
+//!     "clock": 3,
+//!     "renditions": []
+//!   }
+//! }
+//! ```
+//!
+//! `type` is one of `add`, `update`, `fork`, `delete`, `snapshot`, `access`
+//! or `policy`, and `data` holds the fields of the matching packet struct,
+//! e.g. `AddPacket`, under the same names, plus `timestamp`: the time in the
+//! id, in UTC. Absent values are `null`, an access's `kind` is `pasted`,
+//! `previewed` or `executed`, a policy's `conflict_policy` is
+//! `last_writer_wins`, `first_wins` or `keep_both_as_fork`, and a version is
+//! `{"clock": 3, "id": "..."}`.
+//! Fields may be added, but not renamed or removed.
+
+use scru128::Scru128Id;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use ssri::Integrity;
+
+use crate::source::Source;
+use crate::store::{
+    AccessKind, AccessPacket, AddPacket, DeletePacket, ForkPacket, Packet, PolicyPacket,
+    SnapshotPacket, UpdatePacket, Version,
+};
+use crate::view::ConflictPolicy;
+
+pub fn to_json(packet: &Packet) -> String {
+    serde_json::to_string(&Wire::from(packet)).unwrap()
+}
+
+pub fn to_value(packet: &Packet) -> serde_json::Value {
+    serde_json::to_value(Wire::from(packet)).unwrap()
+}
+
+/// Reads a packet written by `to_json`. Its `timestamp` is ignored, the id
+/// being the source of truth.
+pub fn from_json(json: &str) -> Result<Packet, serde_json::Error> {
+    serde_json::from_str::<Wire>(json).map(Packet::from)
+}
+
+/// Writes `packet` in the wire format with any serializer, e.g. for
+/// `#[serde(serialize_with = "wire::serialize")]`.
+pub fn serialize<S: Serializer>(packet: &Packet, serializer: S) -> Result<S::Ok, S::Error> {
+    Wire::from(packet).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Packet, D::Error> {
+    Wire::deserialize(deserializer).map(Packet::from)
+}
+
+/// `millis` since the epoch as an ISO 8601 UTC time, e.g.
+/// `2024-05-01T09:30:00.123Z`.
+pub fn timestamp(millis: u64) -> String {
+    let secs = millis / 1000;
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+    // days to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        millis % 1000
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+enum Wire {
+    Add(WireAdd),
+    Update(WireUpdate),
+    Fork(WireFork),
+    Delete(WireDelete),
+    Snapshot(WireSnapshot),
+    Access(WireAccess),
+    Policy(WirePolicy),
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireSource {
+    app: String,
+    window_title: Option<String>,
+    device: Option<String>,
+    url: Option<String>,
+    path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WireAccessKind {
+    Pasted,
+    Previewed,
+    Executed,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WireConflictPolicy {
+    LastWriterWins,
+    FirstWins,
+    KeepBothAsFork,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireAdd {
+    id: Scru128Id,
+    #[serde(skip_deserializing)]
+    timestamp: String,
+    hash: Integrity,
+    stack_id: Option<Scru128Id>,
+    source: Option<WireSource>,
+    device_id: Option<String>,
+    clock: Option<u64>,
+    renditions: Vec<Integrity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireUpdate {
+    id: Scru128Id,
+    #[serde(skip_deserializing)]
+    timestamp: String,
+    source_id: Scru128Id,
+    hash: Option<Integrity>,
+    stack_id: Option<Scru128Id>,
+    source: Option<WireSource>,
+    device_id: Option<String>,
+    clock: Option<u64>,
+    base: Option<Version>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireFork {
+    id: Scru128Id,
+    #[serde(skip_deserializing)]
+    timestamp: String,
+    source_id: Scru128Id,
+    hash: Option<Integrity>,
+    stack_id: Option<Scru128Id>,
+    source: Option<WireSource>,
+    device_id: Option<String>,
+    clock: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireDelete {
+    id: Scru128Id,
+    #[serde(skip_deserializing)]
+    timestamp: String,
+    source_id: Scru128Id,
+    device_id: Option<String>,
+    clock: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireSnapshot {
+    id: Scru128Id,
+    #[serde(skip_deserializing)]
+    timestamp: String,
+    hash: Integrity,
+    stack_id: Option<Scru128Id>,
+    source: Option<WireSource>,
+    device_id: Option<String>,
+    touched: Vec<Scru128Id>,
+    version: Version,
+    renditions: Vec<Integrity>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireAccess {
+    id: Scru128Id,
+    #[serde(skip_deserializing)]
+    timestamp: String,
+    source_id: Scru128Id,
+    kind: WireAccessKind,
+    device_id: Option<String>,
+    clock: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WirePolicy {
+    id: Scru128Id,
+    #[serde(skip_deserializing)]
+    timestamp: String,
+    conflict_policy: WireConflictPolicy,
+    device_id: Option<String>,
+    clock: Option<u64>,
+}
+
+impl From<&Source> for WireSource {
+    fn from(source: &Source) -> Self {
+        WireSource {
+            app: source.app.clone(),
+            window_title: source.window_title.clone(),
+            device: source.device.clone(),
+            url: source.url.clone(),
+            path: source.path.clone(),
+        }
+    }
+}
+
+impl From<WireSource> for Source {
+    fn from(source: WireSource) -> Self {
+        Source {
+            app: source.app,
+            window_title: source.window_title,
+            device: source.device,
+            url: source.url,
+            path: source.path,
+        }
+    }
+}
+
+impl From<&Packet> for Wire {
+    fn from(packet: &Packet) -> Self {
+        let timestamp = timestamp(packet.id().timestamp());
+        let source = |source: &Option<Source>| source.as_ref().map(WireSource::from);
+        match packet.clone() {
+            Packet::Add(packet) => Wire::Add(WireAdd {
+                id: packet.id,
+                timestamp,
+                hash: packet.hash,
+                stack_id: packet.stack_id,
+                source: source(&packet.source),
+                device_id: packet.device_id,
+                clock: packet.clock,
+                renditions: packet.renditions,
+            }),
+            Packet::Update(packet) => Wire::Update(WireUpdate {
+                id: packet.id,
+                timestamp,
+                source_id: packet.source_id,
+                hash: packet.hash,
+                stack_id: packet.stack_id,
+                source: source(&packet.source),
+                device_id: packet.device_id,
+                clock: packet.clock,
+                base: packet.base,
+            }),
+            Packet::Fork(packet) => Wire::Fork(WireFork {
+                id: packet.id,
+                timestamp,
+                source_id: packet.source_id,
+                hash: packet.hash,
+                stack_id: packet.stack_id,
+                source: source(&packet.source),
+                device_id: packet.device_id,
+                clock: packet.clock,
+            }),
+            Packet::Delete(packet) => Wire::Delete(WireDelete {
+                id: packet.id,
+                timestamp,
+                source_id: packet.source_id,
+                device_id: packet.device_id,
+                clock: packet.clock,
+            }),
+            Packet::Snapshot(packet) => Wire::Snapshot(WireSnapshot {
+                id: packet.id,
+                timestamp,
+                hash: packet.hash,
+                stack_id: packet.stack_id,
+                source: source(&packet.source),
+                device_id: packet.device_id,
+                touched: packet.touched,
+                version: packet.version,
+                renditions: packet.renditions,
+            }),
+            Packet::Access(packet) => Wire::Access(WireAccess {
+                id: packet.id,
+                timestamp,
+                source_id: packet.source_id,
+                kind: match packet.kind {
+                    AccessKind::Pasted => WireAccessKind::Pasted,
+                    AccessKind::Previewed => WireAccessKind::Previewed,
+                    AccessKind::Executed => WireAccessKind::Executed,
+                },
+                device_id: packet.device_id,
+                clock: packet.clock,
+            }),
+            Packet::Policy(packet) => Wire::Policy(WirePolicy {
+                id: packet.id,
+                timestamp,
+                conflict_policy: match packet.conflict_policy {
+                    ConflictPolicy::LastWriterWins => WireConflictPolicy::LastWriterWins,
+                    ConflictPolicy::FirstWins => WireConflictPolicy::FirstWins,
+                    ConflictPolicy::KeepBothAsFork => WireConflictPolicy::KeepBothAsFork,
+                },
+                device_id: packet.device_id,
+                clock: packet.clock,
+            }),
+        }
+    }
+}
+
+impl From<Wire> for Packet {
+    fn from(wire: Wire) -> Self {
+        match wire {
+            Wire::Add(packet) => Packet::Add(AddPacket {
+                id: packet.id,
+                hash: packet.hash,
+                stack_id: packet.stack_id,
+                source: packet.source.map(Source::from),
+                device_id: packet.device_id,
+                clock: packet.clock,
+                renditions: packet.renditions,
+            }),
+            Wire::Update(packet) => Packet::Update(UpdatePacket {
+                id: packet.id,
+                source_id: packet.source_id,
+                hash: packet.hash,
+                stack_id: packet.stack_id,
+                source: packet.source.map(Source::from),
+                device_id: packet.device_id,
+                clock: packet.clock,
+                base: packet.base,
+            }),
+            Wire::Fork(packet) => Packet::Fork(ForkPacket {
+                id: packet.id,
+                source_id: packet.source_id,
+                hash: packet.hash,
+                stack_id: packet.stack_id,
+                source: packet.source.map(Source::from),
+                device_id: packet.device_id,
+                clock: packet.clock,
+            }),
+            Wire::Delete(packet) => Packet::Delete(DeletePacket {
+                id: packet.id,
+                source_id: packet.source_id,
+                device_id: packet.device_id,
+                clock: packet.clock,
+            }),
+            Wire::Snapshot(packet) => Packet::Snapshot(SnapshotPacket {
+                id: packet.id,
+                hash: packet.hash,
+                stack_id: packet.stack_id,
+                source: packet.source.map(Source::from),
+                device_id: packet.device_id,
+                touched: packet.touched,
+                version: packet.version,
+                renditions: packet.renditions,
+            }),
+            Wire::Access(packet) => Packet::Access(AccessPacket {
+                id: packet.id,
+                source_id: packet.source_id,
+                kind: match packet.kind {
+                    WireAccessKind::Pasted => AccessKind::Pasted,
+                    WireAccessKind::Previewed => AccessKind::Previewed,
+                    WireAccessKind::Executed => AccessKind::Executed,
+                },
+                device_id: packet.device_id,
+                clock: packet.clock,
+            }),
+            Wire::Policy(packet) => Packet::Policy(PolicyPacket {
+                id: packet.id,
+                conflict_policy: match packet.conflict_policy {
+                    WireConflictPolicy::LastWriterWins => ConflictPolicy::LastWriterWins,
+                    WireConflictPolicy::FirstWins => ConflictPolicy::FirstWins,
+                    WireConflictPolicy::KeepBothAsFork => ConflictPolicy::KeepBothAsFork,
+                },
+                device_id: packet.device_id,
+                clock: packet.clock,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::store::{MimeType, Store};
+
+    #[test]
+    fn test_wire_format() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(timestamp(1714555800123), "2024-05-01T09:30:00.123Z");
+        assert_eq!(timestamp(951782400000), "2000-02-29T00:00:00.000Z");
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = Store::new(dir.path().to_str().unwrap());
+        let source = Source {
+            url: Some("https://example.com".into()),
+            ..Source::new("Safari")
+        };
+        let add = store
+            .add(b"hello", MimeType::TextPlain, None, Some(source))
+            .unwrap();
+        let access = store.record_access(add.id(), AccessKind::Pasted);
+        let delete = store.delete(add.id()).unwrap();
+        let policy = store.set_conflict_policy(ConflictPolicy::KeepBothAsFork);
+
+        let value = to_value(&add);
+        assert_eq!(value["type"], "add");
+        assert_eq!(value["data"]["id"], add.id().to_string());
+        assert_eq!(value["data"]["timestamp"], timestamp(add.id().timestamp()));
+        assert_eq!(value["data"]["source"]["app"], "Safari");
+        assert_eq!(value["data"]["source"]["url"], "https://example.com");
+        assert_eq!(value["data"]["stack_id"], serde_json::Value::Null);
+        assert_eq!(to_value(&access)["data"]["kind"], "pasted");
+        assert_eq!(to_value(&delete)["data"]["source_id"], add.id().to_string());
+        assert_eq!(to_value(&policy)["type"], "policy");
+        assert_eq!(
+            to_value(&policy)["data"]["conflict_policy"],
+            "keep_both_as_fork"
+        );
+
+        for packet in [add, access, delete, policy] {
+            assert_eq!(from_json(&to_json(&packet)).unwrap(), packet);
+        }
+        assert!(from_json(r#"{"type": "Add", "data": {}}"#).is_err());
+    }
+}
@@ -0,0 +1,19 @@
+// What the generated bindings don't give a menu-bar app out of the box.
+// Add this file to the target alongside the generated `s2.swift`.
+
+import AppKit
+
+extension Store {
+    /// Packets as they're written from now on, until the task iterating
+    /// over them is cancelled.
+    func packets() -> AsyncStream<Packet> {
+        let stream = watch()
+        return AsyncStream(unfolding: { await stream.next() }, onCancel: { stream.close() })
+    }
+
+    /// The item's content as an image, or nil if it isn't one.
+    func nsImage(id: String) throws -> NSImage? {
+        guard let image = try image(id: id) else { return nil }
+        return NSImage(data: image.data)
+    }
+}